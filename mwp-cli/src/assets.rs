@@ -0,0 +1,129 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use camino::{Utf8Path, Utf8PathBuf};
+use grass::OutputStyle;
+
+/// Directory in the wiki root with stylesheet and script additions.
+pub const ASSETS_DIR: &str = "assets";
+
+/// Partial imported before the built-in stylesheet, to override its `!default` variables.
+const VARIABLES_FILE: &str = "_variables.scss";
+
+/// Stylesheet imported after the built-in one, to add or override rules.
+const STYLES_FILE: &str = "styles.scss";
+
+const BUILTIN_STYLES: &str = include_str!("../assets/styles.scss");
+const BUILTIN_SCRIPT: &str = include_str!("../assets/script.js");
+
+/// Compiles the built-in stylesheet together with the wiki's
+/// `assets/_variables.scss` and `assets/styles.scss`, when they exist.
+pub fn compile_styles(wiki_root: &Utf8Path) -> Result<String> {
+    let dir = wiki_root.join(ASSETS_DIR);
+    let import = |name: &str| {
+        dir.join(name)
+            .is_file()
+            .then(|| format!("@import \"{}\";\n", import_name(name)))
+    };
+
+    let mut source = String::new();
+    source.extend(import(VARIABLES_FILE));
+    source.push_str(BUILTIN_STYLES);
+    source.push('\n');
+    source.extend(import(STYLES_FILE));
+
+    // Errors already name the file and line they occur on.
+    grass::from_string(
+        source,
+        &grass::Options::default()
+            .style(OutputStyle::Compressed)
+            .load_path(&dir),
+    )
+    .map_err(|err| anyhow!("{}", err))
+    .context("failed to compile styles")
+}
+
+/// The built-in script followed by every `.js` file in the wiki's `assets/`
+/// directory, in file name order.
+pub fn bundle_scripts(wiki_root: &Utf8Path) -> Result<String> {
+    let mut bundle = String::from(BUILTIN_SCRIPT);
+    for path in scripts(&wiki_root.join(ASSETS_DIR))? {
+        let script =
+            fs::read_to_string(&path).with_context(|| format!("failed to read script {}", path))?;
+        bundle.push_str(&format!("\n// {}\n", path.file_name().unwrap_or_default()));
+        bundle.push_str(&script);
+    }
+    Ok(bundle)
+}
+
+/// Whether a file at a root-relative path is compiled into the bundled
+/// assets instead of being copied into the output as it is: stylesheets
+/// anywhere under `assets/`, scripts directly in it.
+pub fn is_bundled(relative: &Path) -> bool {
+    match relative.extension() {
+        Some(ext) if ext == "scss" => relative.starts_with(ASSETS_DIR),
+        Some(ext) if ext == "js" => relative.parent() == Some(Path::new(ASSETS_DIR)),
+        _ => false,
+    }
+}
+
+/// Name a partial is imported by, without the leading `_` and the extension.
+fn import_name(file: &str) -> &str {
+    file.trim_start_matches('_').trim_end_matches(".scss")
+}
+
+fn scripts(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut scripts = Vec::new();
+    for entry in dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {}", dir))?
+    {
+        let path = entry?.into_path();
+        if path.is_file() && path.extension() == Some("js") {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_with_overrides() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        let dir = root.join(ASSETS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(VARIABLES_FILE), "$foreground: #123456;\n").unwrap();
+        fs::write(dir.join(STYLES_FILE), "@import \"rules\";\n").unwrap();
+        fs::write(dir.join("_rules.scss"), ".custom { color: $background; }\n").unwrap();
+        fs::write(dir.join("b.js"), "console.log('b');\n").unwrap();
+        fs::write(dir.join("a.js"), "console.log('a');\n").unwrap();
+
+        let css = compile_styles(root).unwrap();
+        assert!(css.contains("--foreground: #123456"));
+        assert!(css.ends_with(".custom{color:#fff}"));
+
+        let js = bundle_scripts(root).unwrap();
+        assert!(js.starts_with(BUILTIN_SCRIPT));
+        assert!(js.ends_with("// a.js\nconsole.log('a');\n\n// b.js\nconsole.log('b');\n"));
+
+        fs::write(dir.join("_rules.scss"), ".custom {\n  color: $nope;\n}\n").unwrap();
+        let err = compile_styles(root).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("Undefined variable"), "{message}");
+        assert!(message.contains("assets/_rules.scss:2:10"), "{message}");
+
+        assert!(is_bundled(Path::new("assets/site.scss")));
+        assert!(is_bundled(Path::new("assets/partials/_x.scss")));
+        assert!(!is_bundled(Path::new("assets/vendor/lib.js")));
+        assert!(!is_bundled(Path::new("assets/font.woff2")));
+    }
+}
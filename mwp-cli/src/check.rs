@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashSet};
+
+use mwp_content::{Content, Node, Page, slugify};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    BrokenLink,
+    MissingAnchor,
+    OrphanPage,
+    DuplicateTitle,
+}
+
+impl ProblemKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProblemKind::BrokenLink => "broken_link",
+            ProblemKind::MissingAnchor => "missing_anchor",
+            ProblemKind::OrphanPage => "orphan_page",
+            ProblemKind::DuplicateTitle => "duplicate_title",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    pub kind: ProblemKind,
+    pub page: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub errors: usize,
+    pub warnings: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    fn new(mut problems: Vec<Problem>) -> Self {
+        problems.sort();
+        problems.dedup();
+        let errors = problems
+            .iter()
+            .filter(|p| p.severity == Severity::Error)
+            .count();
+        Report {
+            errors,
+            warnings: problems.len() - errors,
+            problems,
+        }
+    }
+
+    pub fn print_human(&self) {
+        for problem in &self.problems {
+            println!(
+                "{}[{}] {}: {}",
+                problem.severity.as_str(),
+                problem.kind.as_str(),
+                problem.page,
+                problem.message
+            );
+        }
+        println!("{} error(s), {} warning(s)", self.errors, self.warnings);
+    }
+}
+
+/// Checks the wiki for broken internal links, missing anchors, orphan pages
+/// and duplicate titles.
+pub fn check(content: &Content) -> Report {
+    let mut pages: Vec<&Page> = content.all().values().collect();
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut problems = Vec::new();
+    for page in &pages {
+        check_links(content, page, &mut problems);
+    }
+    check_orphans(content, &pages, &mut problems);
+    check_duplicate_titles(&pages, &mut problems);
+
+    Report::new(problems)
+}
+
+fn check_links(content: &Content, page: &Page, problems: &mut Vec<Problem>) {
+    for link in &page.wiki_links {
+        match &link.resolved {
+            None => problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::BrokenLink,
+                page: page.path.clone(),
+                message: format!("wiki link [[{}]] matches no page", link.target),
+            }),
+            Some(target) => check_anchor(content, page, target, link.fragment.as_deref(), problems),
+        }
+    }
+
+    for embed in &page.embeds {
+        match &embed.resolved {
+            None => problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::BrokenLink,
+                page: page.path.clone(),
+                message: format!("embed ![[{}]] matches no page", embed.target),
+            }),
+            Some(target) => {
+                check_anchor(content, page, target, embed.fragment.as_deref(), problems)
+            }
+        }
+    }
+
+    for link in &page.internal_links {
+        if content.get(&link.path).is_none() {
+            problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::BrokenLink,
+                page: page.path.clone(),
+                message: format!("link to {} matches no page", link.path),
+            });
+            continue;
+        }
+        check_anchor(
+            content,
+            page,
+            &link.path,
+            link.fragment.as_deref(),
+            problems,
+        );
+    }
+}
+
+fn check_anchor(
+    content: &Content,
+    page: &Page,
+    target: &str,
+    fragment: Option<&str>,
+    problems: &mut Vec<Problem>,
+) {
+    let (Some(fragment), Some(target_page)) = (fragment, content.get(target)) else {
+        return;
+    };
+    if fragment.is_empty() {
+        return;
+    }
+
+    let slug = slugify(fragment);
+    if !target_page
+        .headings
+        .iter()
+        .any(|heading| heading.id == fragment || heading.id == slug)
+    {
+        problems.push(Problem {
+            severity: Severity::Error,
+            kind: ProblemKind::MissingAnchor,
+            page: page.path.clone(),
+            message: format!("link to {}#{} matches no heading", target, fragment),
+        });
+    }
+}
+
+fn check_orphans(content: &Content, pages: &[&Page], problems: &mut Vec<Problem>) {
+    let mut in_tree = HashSet::new();
+    collect_tree_paths(&content.build_tree(), &mut in_tree);
+
+    for page in pages {
+        if page.path == "/" || in_tree.contains(page.path.as_str()) {
+            continue;
+        }
+        if content.backlinks(&page.path).is_empty() {
+            problems.push(Problem {
+                severity: Severity::Warning,
+                kind: ProblemKind::OrphanPage,
+                page: page.path.clone(),
+                message: "no page links here and it is not part of the navigation".into(),
+            });
+        }
+    }
+}
+
+fn collect_tree_paths(nodes: &[Node], paths: &mut HashSet<String>) {
+    for node in nodes {
+        paths.insert(node.path.clone());
+        collect_tree_paths(&node.children, paths);
+    }
+}
+
+fn check_duplicate_titles(pages: &[&Page], problems: &mut Vec<Problem>) {
+    let mut by_title: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for page in pages {
+        by_title
+            .entry(page.title.as_str())
+            .or_default()
+            .push(page.path.as_str());
+    }
+
+    for (title, paths) in by_title {
+        if paths.len() < 2 {
+            continue;
+        }
+        for path in &paths {
+            let others: Vec<&str> = paths.iter().copied().filter(|p| p != path).collect();
+            let title = if title.is_empty() { "(empty)" } else { title };
+            problems.push(Problem {
+                severity: Severity::Warning,
+                kind: ProblemKind::DuplicateTitle,
+                page: path.to_string(),
+                message: format!("title {title:?} is also used by {}", others.join(", ")),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_reports_problems() {
+        let content = Content::from_pages(
+            [
+                (
+                    "/",
+                    "# Home\n\n[[git#Usage]] and [[git#Nope]]\n\n![[gone]]\n",
+                ),
+                ("/git", "# Git\n\n## Usage\n\n[broken](missing.md)\n"),
+                ("/a/b", "# Git\n"),
+            ]
+            .into_iter()
+            .map(|(path, markdown)| Page::from(path, markdown.into()).unwrap()),
+        );
+
+        let report = check(&content);
+        let found: Vec<(ProblemKind, &str)> = report
+            .problems
+            .iter()
+            .map(|p| (p.kind, p.page.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (ProblemKind::BrokenLink, "/"),
+                (ProblemKind::BrokenLink, "/git"),
+                (ProblemKind::MissingAnchor, "/"),
+                (ProblemKind::OrphanPage, "/a/b"),
+                (ProblemKind::DuplicateTitle, "/a/b"),
+                (ProblemKind::DuplicateTitle, "/git"),
+            ]
+        );
+        assert_eq!((report.errors, report.warnings), (3, 3));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use mwp_content::{MarkdownOptions, Walker};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::images::ImageFormat;
+
+/// Name of the site configuration file looked up in the wiki root.
+pub const CONFIG_FILE: &str = "mwp.toml";
+
+/// Site configuration read from `mwp.toml`, every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Branding and metadata of the generated site.
+    pub site: SiteConfig,
+
+    /// Files of the wiki tree that make up the site.
+    pub content: ContentConfig,
+
+    /// Fetching of remote pages for the search index.
+    pub fetch: FetchConfig,
+
+    /// Markdown extensions used when parsing pages.
+    pub markdown: MarkdownOptions,
+
+    /// Code highlighting themes.
+    pub highlight: HighlightConfig,
+
+    /// Responsive image processing.
+    pub images: ImagesConfig,
+
+    /// Generated `robots.txt`.
+    pub robots: RobotsConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    /// Name of the site, shown in the navigation and appended to page titles.
+    pub title: String,
+
+    /// HTML rendered in the page footer, omitted when empty.
+    pub footer: String,
+
+    /// Label of the root page in breadcrumbs.
+    pub root_label: String,
+
+    /// Language of the pages, used for the `lang` attribute and search stemming.
+    pub language: String,
+
+    /// Public URL the site is deployed at, used for canonical links.
+    pub base_url: Option<Url>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            title: "Wiki".into(),
+            footer: String::new(),
+            root_label: "Wiki".into(),
+            language: "en".into(),
+            base_url: None,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// Absolute URL of a page when the base URL is configured, with each
+    /// segment of the page URL percent-encoded.
+    pub fn canonical_url(&self, page_url: &str) -> Option<String> {
+        let mut url = self.base_url.clone()?;
+        url.path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .extend(page_url.trim_start_matches('/').split('/'));
+        Some(url.into())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentConfig {
+    /// Names of files and directories to skip, on top of the built-in list.
+    pub ignore: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchConfig {
+    /// Maximum number of concurrent HTTP downloads.
+    pub concurrency: usize,
+
+    /// Directory for caching fetched remote pages, relative to the wiki root.
+    pub cache_dir: Option<Utf8PathBuf>,
+
+    /// Revalidate cached pages older than this many hours.
+    pub cache_ttl_hours: u64,
+
+    /// Use cached pages only, without any network requests.
+    pub offline: bool,
+
+    /// User agent sent with every request.
+    pub user_agent: String,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            concurrency: 10,
+            cache_dir: None,
+            cache_ttl_hours: 168,
+            offline: false,
+            user_agent: "mwp-indexer/0.2 (+https://github.com/matoous/mwp)".into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightConfig {
+    /// Theme used for code blocks.
+    pub theme: String,
+
+    /// Theme used for code blocks when the browser prefers a dark color scheme.
+    pub dark_theme: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// Whether to process images, also enabled by `mwp build --images`.
+    pub enabled: bool,
+
+    /// Widths images are resized to, larger ones are skipped for each image.
+    pub widths: Vec<u32>,
+
+    /// Formats every resized image is encoded to.
+    pub formats: Vec<ImageFormat>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RobotsConfig {
+    /// Paths crawlers are asked not to visit, such as `/private/`.
+    pub disallow: Vec<String>,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        ImagesConfig {
+            enabled: false,
+            widths: vec![480, 960, 1600],
+            formats: vec![ImageFormat::Webp, ImageFormat::Avif],
+        }
+    }
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            theme: "InspiredGitHub".into(),
+            dark_theme: "base16-ocean.dark".into(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the wiki root, falling back to the
+    /// defaults when the file doesn't exist.
+    pub fn load(root: &Utf8Path) -> Result<Self> {
+        let path = root.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config {}", path))?;
+        Self::parse(&raw).with_context(|| format!("invalid config {}", path))
+    }
+
+    /// Walker over the wiki tree that skips the configured names.
+    pub fn walker(&self, root: &Utf8Path) -> Walker {
+        Walker::new(root).ignore(self.content.ignore.iter().cloned())
+    }
+
+    fn parse(raw: &str) -> Result<Self> {
+        Ok(toml::from_str(raw)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_markdown_options() {
+        let config = Config::parse("[markdown]\nsmart_punctuation = false\n").unwrap();
+        assert_eq!(
+            config.markdown,
+            MarkdownOptions {
+                smart_punctuation: false,
+                ..MarkdownOptions::default()
+            }
+        );
+
+        assert!(Config::parse("[markdown]\nemoji = true\n").is_err());
+        assert_eq!(config.highlight.theme, "InspiredGitHub");
+    }
+
+    #[test]
+    fn parse_site_and_fetch() {
+        let config = Config::parse(
+            "[site]\ntitle = \"Team Wiki\"\nbase_url = \"https://wiki.example.com/docs/\"\n\n\
+             [content]\nignore = [\"drafts\"]\n\n[fetch]\nconcurrency = 4\n\n[robots]\ndisallow = [\"/private/\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.site.title, "Team Wiki");
+        assert_eq!(config.site.root_label, "Wiki");
+        assert_eq!(
+            config.site.canonical_url("/tools/git/").as_deref(),
+            Some("https://wiki.example.com/docs/tools/git/")
+        );
+        assert_eq!(
+            config.site.canonical_url("/notes/café 50%/").as_deref(),
+            Some("https://wiki.example.com/docs/notes/caf%C3%A9%2050%25/")
+        );
+        assert_eq!(config.content.ignore, ["drafts"]);
+        assert_eq!(config.fetch.concurrency, 4);
+        assert_eq!(config.fetch.cache_ttl_hours, 168);
+        assert_eq!(config.robots.disallow, ["/private/"]);
+
+        assert!(Config::parse("[site]\nbase_url = \"not a url\"\n").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// Writes a file unless it has the same contents already, keeping its
+/// modification time for servers and deploy tools that rely on it.
+pub fn write_if_changed(path: &Utf8Path, contents: &[u8]) -> Result<()> {
+    if fs::read(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+    fs::write(path, contents).with_context(|| format!("failed to write {}", path))
+}
+
+/// Whether `destination` is a copy of `source` made after its last change.
+pub fn is_up_to_date(source: &Path, destination: &Path) -> bool {
+    let (Ok(source), Ok(destination)) = (fs::metadata(source), fs::metadata(destination)) else {
+        return false;
+    };
+    match (source.modified(), destination.modified()) {
+        (Ok(source_modified), Ok(destination_modified)) => {
+            source.len() == destination.len() && destination_modified >= source_modified
+        }
+        _ => false,
+    }
+}
+
+/// Paths of all files under `dir`, recursively.
+pub fn list_files(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut files = Vec::new();
+    for entry in dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {}", dir))?
+    {
+        let path = entry?.into_path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,361 @@
+use std::{collections::HashMap, fs, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use image::{
+    DynamicImage, ExtendedColorType, ImageEncoder,
+    codecs::{avif::AvifEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use crate::{config::ImagesConfig, files::is_up_to_date};
+
+/// Bumped whenever the encoding changes, invalidating cached variants.
+const CACHE_VERSION: &str = "1";
+
+/// Raster image extensions the pipeline processes.
+const EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Webp,
+    Avif,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Avif => "image/avif",
+        }
+    }
+
+    fn encode(self, image: &DynamicImage, path: &Path) -> Result<()> {
+        let rgba = image.to_rgba8();
+        let file = fs::File::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let writer = BufWriter::new(file);
+        let (width, height) = rgba.dimensions();
+        match self {
+            ImageFormat::Webp => WebPEncoder::new_lossless(writer).write_image(
+                &rgba,
+                width,
+                height,
+                ExtendedColorType::Rgba8,
+            ),
+            ImageFormat::Avif => AvifEncoder::new_with_speed_quality(writer, 8, 70).write_image(
+                &rgba,
+                width,
+                height,
+                ExtendedColorType::Rgba8,
+            ),
+        }
+        .with_context(|| format!("failed to encode {}", path.display()))
+    }
+}
+
+/// Dimensions of a processed image and the widths it was resized to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Processed {
+    width: u32,
+    height: u32,
+    widths: Vec<u32>,
+}
+
+/// Resizes and re-encodes the images pages refer to, and rewrites their
+/// `<img>` tags into responsive `<picture>` elements.
+///
+/// Variants are cached by a hash of the source image and the settings, and
+/// written next to the original image in the output.
+pub struct ImagePipeline {
+    wiki_root: Utf8PathBuf,
+    output_dir: Utf8PathBuf,
+    cache_dir: Utf8PathBuf,
+    widths: Vec<u32>,
+    formats: Vec<ImageFormat>,
+    processed: HashMap<String, Option<Processed>>,
+    variants: Vec<Utf8PathBuf>,
+}
+
+impl ImagePipeline {
+    pub fn new(
+        config: &ImagesConfig,
+        wiki_root: &Utf8Path,
+        output_dir: &Utf8Path,
+        cache_dir: &Utf8Path,
+    ) -> Self {
+        ImagePipeline {
+            wiki_root: wiki_root.to_owned(),
+            output_dir: output_dir.to_owned(),
+            cache_dir: cache_dir.to_owned(),
+            widths: config.widths.clone(),
+            formats: config.formats.clone(),
+            processed: HashMap::new(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Output paths of the variants the pages rewritten since the last call
+    /// refer to, relative to the output directory.
+    pub fn take_variants(&mut self) -> Vec<Utf8PathBuf> {
+        std::mem::take(&mut self.variants)
+    }
+
+    /// Rewrites the `<img>` tags in rendered page HTML, processing the local
+    /// images they point to.
+    pub fn rewrite(&mut self, html: &str) -> Result<String> {
+        let mut output = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find("<img ") {
+            let Some(end) = rest[start..].find('>').map(|end| start + end) else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+            output.push_str(&self.rewrite_tag(&rest[start..=end])?);
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn rewrite_tag(&mut self, tag: &str) -> Result<String> {
+        let attributes = tag["<img ".len()..tag.len() - 1]
+            .trim_end()
+            .trim_end_matches('/')
+            .trim_end();
+        let lazy = if attributes.contains("loading=") {
+            ""
+        } else {
+            " loading=\"lazy\""
+        };
+
+        let processed = match attribute(attributes, "src") {
+            Some(src) => self.process(src)?.map(|processed| (src, processed)),
+            None => None,
+        };
+        let Some((src, processed)) = processed else {
+            return Ok(format!("<img {attributes}{lazy} />"));
+        };
+
+        let stem = src.rsplit_once('.').map_or(src, |(stem, _)| stem);
+        let decoded = percent_decode_str(stem).decode_utf8_lossy();
+        for width in &processed.widths {
+            for format in &self.formats {
+                self.variants.push(Utf8PathBuf::from(format!(
+                    "{}.{width}w.{}",
+                    decoded.trim_start_matches('/'),
+                    format.extension()
+                )));
+            }
+        }
+
+        let largest = processed.widths.last().copied().unwrap_or(processed.width);
+        let mut html = String::from("<picture>");
+        for format in &self.formats {
+            let srcset: Vec<String> = processed
+                .widths
+                .iter()
+                .map(|width| format!("{stem}.{width}w.{} {width}w", format.extension()))
+                .collect();
+            html.push_str(&format!(
+                "<source type=\"{}\" srcset=\"{}\" sizes=\"(max-width: {largest}px) 100vw, {largest}px\">",
+                format.mime(),
+                srcset.join(", ")
+            ));
+        }
+        html.push_str(&format!(
+            "<img {attributes} width=\"{}\" height=\"{}\"{lazy} decoding=\"async\" /></picture>",
+            processed.width, processed.height
+        ));
+        Ok(html)
+    }
+
+    fn process(&mut self, src: &str) -> Result<Option<Processed>> {
+        if let Some(processed) = self.processed.get(src) {
+            return Ok(processed.clone());
+        }
+
+        let path = percent_decode_str(src).decode_utf8_lossy().into_owned();
+        // Only paths that stay inside the wiki, the variants are written to
+        // the same path in the output.
+        let is_image = src.starts_with('/')
+            && !src.starts_with("//")
+            && Utf8Path::new(&path).components().all(|component| {
+                matches!(component, Utf8Component::RootDir | Utf8Component::Normal(_))
+            })
+            && Utf8Path::new(&path).extension().is_some_and(|ext| {
+                EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            });
+        let processed = if is_image {
+            self.process_file(path.trim_start_matches('/'))?
+        } else {
+            None
+        };
+
+        self.processed.insert(src.to_string(), processed.clone());
+        Ok(processed)
+    }
+
+    fn process_file(&self, relative: &str) -> Result<Option<Processed>> {
+        // Missing files are reported when the page is built.
+        let Ok(bytes) = fs::read(self.wiki_root.join(relative)) else {
+            return Ok(None);
+        };
+
+        let mut hasher = Sha1::new();
+        hasher.update(CACHE_VERSION);
+        hasher.update(format!("{:?}{:?}", self.widths, self.formats));
+        hasher.update(&bytes);
+        let cache_dir = self.cache_dir.join(format!("{:x}", hasher.finalize()));
+        let meta_path = cache_dir.join("meta.json");
+
+        let processed = match fs::read(&meta_path)
+            .ok()
+            .and_then(|meta| serde_json::from_slice::<Processed>(&meta).ok())
+        {
+            Some(processed) => processed,
+            None => {
+                let image = match image::load_from_memory(&bytes) {
+                    Ok(image) => image,
+                    Err(err) => {
+                        warn!(image = %relative, error = %err, "skipping image that can't be decoded");
+                        return Ok(None);
+                    }
+                };
+                fs::create_dir_all(&cache_dir)
+                    .with_context(|| format!("failed to create {}", cache_dir))?;
+                let processed = self.encode_variants(&image, &cache_dir)?;
+                fs::write(&meta_path, serde_json::to_vec(&processed)?)
+                    .with_context(|| format!("failed to write {}", meta_path))?;
+                processed
+            }
+        };
+
+        let stem = relative.rsplit_once('.').map_or(relative, |(stem, _)| stem);
+        for width in &processed.widths {
+            for format in &self.formats {
+                let cached = cache_dir.join(format!("{width}.{}", format.extension()));
+                let destination = self
+                    .output_dir
+                    .join(format!("{stem}.{width}w.{}", format.extension()));
+                if is_up_to_date(cached.as_std_path(), destination.as_std_path()) {
+                    continue;
+                }
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {}", parent))?;
+                }
+                fs::copy(&cached, &destination)
+                    .with_context(|| format!("failed to copy {}", cached))?;
+            }
+        }
+
+        Ok(Some(processed))
+    }
+
+    fn encode_variants(&self, image: &DynamicImage, cache_dir: &Utf8Path) -> Result<Processed> {
+        let mut widths: Vec<u32> = self
+            .widths
+            .iter()
+            .copied()
+            .filter(|width| *width < image.width())
+            .chain([image.width()])
+            .collect();
+        widths.sort_unstable();
+        widths.dedup();
+
+        for width in &widths {
+            let resized = if *width == image.width() {
+                image.clone()
+            } else {
+                image.resize(*width, u32::MAX, FilterType::Lanczos3)
+            };
+            for format in &self.formats {
+                let path = cache_dir.join(format!("{width}.{}", format.extension()));
+                format.encode(&resized, path.as_std_path())?;
+            }
+        }
+
+        Ok(Processed {
+            width: image.width(),
+            height: image.height(),
+            widths,
+        })
+    }
+}
+
+/// Value of a double quoted attribute in the attribute list of a tag.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let start = attributes.find(&format!("{name}=\""))? + name.len() + 2;
+    let end = attributes[start..].find('"')? + start;
+    Some(&attributes[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_images_into_pictures() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        let (wiki, dist, cache) = (root.join("wiki"), root.join("dist"), root.join("cache"));
+        fs::create_dir_all(wiki.join("img")).unwrap();
+        image::RgbImage::from_pixel(64, 32, image::Rgb([200, 10, 10]))
+            .save(wiki.join("img/red box.png"))
+            .unwrap();
+
+        let config = ImagesConfig {
+            enabled: true,
+            widths: vec![16, 128],
+            formats: vec![ImageFormat::Webp],
+        };
+        let html = "<p><img src=\"/img/red%20box.png\" alt=\"red\" /> <img src=\"https://example.com/a.png\" alt=\"\" /></p>";
+        let mut pipeline = ImagePipeline::new(&config, &wiki, &dist, &cache);
+        let rewritten = pipeline.rewrite(html).unwrap();
+        assert_eq!(
+            rewritten,
+            "<p><picture><source type=\"image/webp\" \
+             srcset=\"/img/red%20box.16w.webp 16w, /img/red%20box.64w.webp 64w\" \
+             sizes=\"(max-width: 64px) 100vw, 64px\">\
+             <img src=\"/img/red%20box.png\" alt=\"red\" width=\"64\" height=\"32\" loading=\"lazy\" decoding=\"async\" />\
+             </picture> <img src=\"https://example.com/a.png\" alt=\"\" loading=\"lazy\" /></p>"
+        );
+        assert!(dist.join("img/red box.16w.webp").is_file());
+        assert!(dist.join("img/red box.64w.webp").is_file());
+        assert!(!dist.join("img/red box.128w.webp").exists());
+
+        // A second pipeline reuses the cached variants.
+        let again = ImagePipeline::new(&config, &wiki, &dist, &cache)
+            .rewrite(html)
+            .unwrap();
+        assert_eq!(rewritten, again);
+        assert_eq!(
+            pipeline.take_variants(),
+            ["img/red box.16w.webp", "img/red box.64w.webp"]
+        );
+
+        // Paths leaving the wiki are neither read nor written.
+        fs::copy(wiki.join("img/red box.png"), root.join("outside.png")).unwrap();
+        let outside = pipeline
+            .rewrite("<img src=\"/../outside.png\" alt=\"\" />")
+            .unwrap();
+        assert!(!outside.contains("<picture>"), "{outside}");
+        assert!(!root.join("outside.16w.webp").exists());
+        assert!(pipeline.take_variants().is_empty());
+    }
+}
@@ -1,27 +1,41 @@
 #![forbid(unsafe_code)]
 
+mod assets;
+mod check;
+mod config;
+mod files;
+mod images;
+mod manifest;
 mod render;
+mod sitemap;
+mod watch;
 
 use std::{
     collections::HashMap,
     fs,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use actix_files::Files;
-use actix_web::{App, HttpServer};
+use actix_files::{Files, NamedFile};
+use actix_web::{
+    App, HttpServer, Responder,
+    dev::{ServiceRequest, ServiceResponse, fn_service},
+};
 use anyhow::{Context, Result, anyhow, bail};
 use camino::{Utf8Path, Utf8PathBuf};
-use clap::{Args, Parser, Subcommand, ValueHint};
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
+use config::{Config, FetchConfig, HighlightConfig, SiteConfig};
+use files::{is_up_to_date, write_if_changed};
 use futures::{StreamExt, stream};
-use grass::OutputStyle;
 use html_escape::encode_safe;
+use images::ImagePipeline;
 use indicatif::{ProgressBar, ProgressStyle};
-use maud::{DOCTYPE, PreEscaped, html};
-use mwp_content::{Content, Node, Page};
+use manifest::{Dependencies, Manifest, PageCache, PageRecord, Source};
+use mwp_content::{Content, Link, MarkdownOptions, Node, Page, Walker, page_url};
 use pagefind::api::PagefindIndex;
 use pagefind::options::PagefindServiceConfig;
-use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag, TagEnd};
+use render::{PageContext, Templates};
 use reqwest::{
     Client, StatusCode,
     header::{ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
@@ -32,24 +46,15 @@ use sha1::{Digest, Sha1};
 use tracing::{info, warn};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 use url::Url;
-use walkdir::{DirEntry, WalkDir};
-
-const IGNORED_DIRECTORIES: &[&str] = &[
-    ".cargo",
-    ".git",
-    ".github",
-    ".mwp-cache",
-    ".obsidian",
-    "dist",
-    "mwp-cli",
-    "public",
-    "static",
-    "target",
-    "vendor",
-];
 
 const MAX_FETCH_ATTEMPTS: usize = 3;
 
+/// Page in the output root that static hosts serve for missing URLs.
+const NOT_FOUND_FILE: &str = "404.html";
+
+/// Stylesheets and scripts written at the root of the output.
+const ASSET_FILES: [&str; 3] = ["styles.css", "script.js", "highlight.css"];
+
 #[derive(Parser, Debug)]
 #[command(
     name = "mwp",
@@ -65,10 +70,12 @@ struct Cli {
 enum CommandKind {
     /// Render wiki markdown into a fully static website
     Build(BuildArgs),
-    /// Generate the static Pagefind search bundle from linked pages
+    /// Generate the static Pagefind search bundle from wiki and linked pages
     Index(IndexArgs),
     /// Serve a built static site locally
     Serve(ServeArgs),
+    /// Check the wiki for broken links, orphan pages and duplicate titles
+    Check(CheckArgs),
 }
 
 #[derive(Args, Debug)]
@@ -81,21 +88,30 @@ struct BuildArgs {
     #[arg(long, value_hint = ValueHint::DirPath, default_value = "dist")]
     output: Utf8PathBuf,
 
-    /// Maximum number of concurrent HTTP downloads when building Pagefind
-    #[arg(long, default_value_t = 10)]
-    concurrency: usize,
+    #[command(flatten)]
+    fetch: FetchArgs,
 
-    /// Directory for caching fetched remote pages
-    #[arg(long, value_hint = ValueHint::DirPath, default_value = ".mwp-cache")]
-    cache_dir: Utf8PathBuf,
+    /// Public URL the site is deployed at, overrides `base_url` in mwp.toml
+    #[arg(long)]
+    base_url: Option<Url>,
 
-    /// Revalidate cached pages older than this many hours
-    #[arg(long, default_value_t = 168)]
-    cache_ttl_hours: u64,
+    /// Skip markdown files that can't be loaded instead of failing the build
+    #[arg(long, default_value_t = false)]
+    lenient: bool,
 
-    /// Do not perform any network requests, use cached pages only
+    /// Resize and re-encode images into responsive variants
     #[arg(long, default_value_t = false)]
-    offline: bool,
+    images: bool,
+
+    /// Remove everything from the output directory before building, refused
+    /// unless it's the output of an earlier build
+    #[arg(long, default_value_t = false)]
+    clean: bool,
+
+    /// List the files that would be removed from the output directory
+    /// without building the site or writing anything
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
 }
 
 #[derive(Args, Debug)]
@@ -108,21 +124,50 @@ struct IndexArgs {
     #[arg(long, value_hint = ValueHint::DirPath, default_value = "dist/pagefind")]
     output: Utf8PathBuf,
 
-    /// Maximum number of concurrent HTTP downloads
-    #[arg(long, default_value_t = 10)]
-    concurrency: usize,
+    #[command(flatten)]
+    fetch: FetchArgs,
+}
 
-    /// Directory for caching fetched remote pages
-    #[arg(long, value_hint = ValueHint::DirPath, default_value = ".mwp-cache")]
-    cache_dir: Utf8PathBuf,
+/// Fetch flags, each overriding the `[fetch]` section of mwp.toml.
+#[derive(Args, Debug, Clone)]
+struct FetchArgs {
+    /// Maximum number of concurrent HTTP downloads [default: 10]
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Directory for caching fetched remote pages [default: .mwp-cache]
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    cache_dir: Option<Utf8PathBuf>,
+
+    /// Revalidate cached pages older than this many hours [default: 168]
+    #[arg(long)]
+    cache_ttl_hours: Option<u64>,
+
+    /// Do not perform any network requests, use cached pages only,
+    /// `--offline=false` overrides `offline` in mwp.toml
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    offline: Option<bool>,
+
+    /// User agent sent with every request
+    #[arg(long)]
+    user_agent: Option<String>,
+}
 
-    /// Revalidate cached pages older than this many hours
-    #[arg(long, default_value_t = 168)]
-    cache_ttl_hours: u64,
+#[derive(Args, Debug)]
+struct CheckArgs {
+    /// Root directory that contains the wiki markdown files
+    #[arg(long, value_hint = ValueHint::DirPath, default_value = ".")]
+    root: Utf8PathBuf,
 
-    /// Do not perform any network requests, use cached pages only
-    #[arg(long, default_value_t = false)]
-    offline: bool,
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Human)]
+    format: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ReportFormat {
+    Human,
+    Json,
 }
 
 #[derive(Args, Debug)]
@@ -134,6 +179,18 @@ struct ServeArgs {
     /// Address to serve on
     #[arg(long, default_value = "127.0.0.1:4444")]
     addr: String,
+
+    /// Build the wiki into the site directory and rebuild it on every change,
+    /// reloading open pages
+    #[arg(long, default_value_t = false)]
+    watch: bool,
+
+    /// Root directory that contains the wiki markdown files, used with --watch
+    #[arg(long, value_hint = ValueHint::DirPath, default_value = ".")]
+    root: Utf8PathBuf,
+
+    #[command(flatten)]
+    fetch: FetchArgs,
 }
 
 #[derive(Debug, Clone)]
@@ -141,6 +198,34 @@ struct FetchSettings {
     cache_dir: Utf8PathBuf,
     cache_ttl: Duration,
     offline: bool,
+    concurrency: usize,
+    user_agent: String,
+}
+
+impl FetchSettings {
+    /// Combines the fetch flags with the config, flags take precedence.
+    ///
+    /// A cache directory from the config is relative to the wiki root, one
+    /// given on the command line to the working directory.
+    fn new(args: &FetchArgs, config: &FetchConfig, wiki_root: &Utf8Path) -> Result<Self> {
+        let cache_dir = match (&args.cache_dir, &config.cache_dir) {
+            (Some(dir), _) => absolute_path(dir)?,
+            (None, Some(dir)) => wiki_root.join(dir),
+            (None, None) => absolute_path(Utf8Path::new(".mwp-cache"))?,
+        };
+        let cache_ttl_hours = args.cache_ttl_hours.unwrap_or(config.cache_ttl_hours);
+
+        Ok(FetchSettings {
+            cache_dir,
+            cache_ttl: Duration::from_secs(cache_ttl_hours.saturating_mul(60 * 60)),
+            offline: args.offline.unwrap_or(config.offline),
+            concurrency: args.concurrency.unwrap_or(config.concurrency),
+            user_agent: args
+                .user_agent
+                .clone()
+                .unwrap_or_else(|| config.user_agent.clone()),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -165,18 +250,9 @@ enum FetchOutcome {
     Cached(DownloadedPage),
 }
 
-#[derive(Debug, Clone)]
-struct WikiLink {
-    title: String,
-    url: Url,
-    tags: Vec<String>,
-    source_path: Utf8PathBuf,
-    starred: bool,
-}
-
 #[derive(Debug, Clone)]
 struct DownloadedPage {
-    link: WikiLink,
+    link: Link,
     html: String,
     etag: Option<String>,
     last_modified: Option<String>,
@@ -185,12 +261,14 @@ struct DownloadedPage {
 #[tokio::main]
 async fn main() -> Result<()> {
     init_tracing();
+    quiet_diagram_panics();
 
     let cli = Cli::parse();
     match cli.command {
         CommandKind::Build(args) => run_build(args).await?,
         CommandKind::Index(args) => run_index(args).await?,
         CommandKind::Serve(args) => run_serve(args).await?,
+        CommandKind::Check(args) => run_check(args).await?,
     }
 
     Ok(())
@@ -199,75 +277,251 @@ async fn main() -> Result<()> {
 async fn run_build(args: BuildArgs) -> Result<()> {
     let wiki_root = absolute_path(&args.root)?;
     let output_dir = absolute_path(&args.output)?;
-    let fetch = FetchSettings {
-        cache_dir: absolute_path(&args.cache_dir)?,
-        cache_ttl: Duration::from_secs(args.cache_ttl_hours.saturating_mul(60 * 60)),
-        offline: args.offline,
-    };
+    let mut config = Config::load(&wiki_root)?;
+    let fetch = FetchSettings::new(&args.fetch, &config.fetch, &wiki_root)?;
+    if let Some(base_url) = args.base_url {
+        config.site.base_url = Some(base_url);
+    }
 
     info!(root = %wiki_root, output = %output_dir, "rendering wiki to static html");
 
-    fs::create_dir_all(output_dir.as_std_path())
-        .with_context(|| format!("failed to create output dir {}", output_dir))?;
+    if args.clean {
+        manifest::clean_output_dir(&output_dir, &wiki_root, args.dry_run)?;
+        if args.dry_run {
+            return Ok(());
+        }
+    }
 
-    let content = Content::from_dir(wiki_root.as_str()).await;
+    config.markdown.cache_dir = Some(fetch.cache_dir.join("diagrams").into_std_path_buf());
+    let walker = config.walker(&wiki_root);
+    let sources = load_sources(&walker, &config.markdown, &fetch.cache_dir, args.lenient)?;
+    let content = Content::from_pages(sources.iter().map(|source| source.page.clone()));
+    for link in content.unresolved_links() {
+        warn!(page = %link.page, target = %link.target, "unresolved wiki link");
+    }
     let navigation_tree = content.build_tree();
-    let mut pages = content.values();
+    let mut pages: Vec<&Page> = content.all().values().collect();
     pages.sort_by(|a, b| a.path.cmp(&b.path));
 
     if pages.is_empty() {
         bail!("no pages discovered under {}", wiki_root);
     }
 
+    let images = args.images || config.images.enabled;
+    let previous = Manifest::load(&output_dir);
+    let mut manifest = Manifest::new(
+        manifest::settings_hash(&wiki_root, &config, images)?,
+        manifest::nav_hash(&navigation_tree),
+    );
+    let mut dependencies = Dependencies::new(&content, &wiki_root, &sources, images);
+    if args.dry_run {
+        for page in pages {
+            manifest.pages.insert(
+                page.path.clone(),
+                PageRecord {
+                    inputs: dependencies.inputs(page),
+                    output: page_output_path(Utf8Path::new(""), &page.path),
+                    output_hash: String::new(),
+                    // Variants are only known once the images are processed,
+                    // assume the page still refers to the same ones.
+                    images: previous
+                        .pages
+                        .get(&page.path)
+                        .map(|record| record.images.clone())
+                        .unwrap_or_default(),
+                },
+            );
+        }
+        manifest.files.insert(NOT_FOUND_FILE.into());
+        manifest.files.extend(ASSET_FILES.map(Utf8PathBuf::from));
+        for (_, relative) in wiki_files(&walker, &output_dir)? {
+            manifest.files.insert(relative);
+        }
+        manifest
+            .files
+            .extend(crawler_files(&wiki_root, &config.site));
+        for path in previous.stale(&manifest) {
+            println!("would remove {}", output_dir.join(path));
+        }
+        return Ok(());
+    }
+
+    fs::create_dir_all(output_dir.as_std_path())
+        .with_context(|| format!("failed to create output dir {}", output_dir))?;
+    let mut writer = PageWriter::new(&wiki_root, &output_dir, &config, &fetch, images, false)?;
+    let mut rendered = 0;
     for page in pages {
-        let rendered = render_page(&content, navigation_tree.as_slice(), &page);
-        write_page(&output_dir, &page, &rendered)?;
+        let inputs = dependencies.inputs(page);
+        if let Some(record) = previous.unchanged(&manifest, &output_dir, &page.path, &inputs) {
+            writer.report(page);
+            manifest.pages.insert(page.path.clone(), record.clone());
+            continue;
+        }
+
+        let written = writer.write(&content, &navigation_tree, page)?;
+        manifest.pages.insert(
+            page.path.clone(),
+            PageRecord {
+                inputs,
+                output: page_output_path(Utf8Path::new(""), &page.path),
+                output_hash: written.hash,
+                images: written.images,
+            },
+        );
+        rendered += 1;
     }
 
-    write_assets(&output_dir)?;
-    generate_pagefind_bundle(
+    info!(
+        rendered,
+        unchanged = manifest.pages.len() - rendered,
+        "wrote pages"
+    );
+    manifest
+        .files
+        .insert(writer.write_not_found(&content, &navigation_tree)?);
+
+    manifest
+        .files
+        .extend(write_assets(&wiki_root, &output_dir, &config.highlight)?);
+    let copied = copy_files(&walker, &output_dir)?;
+    info!(copied = copied.copied, "copied wiki files");
+    manifest.files.extend(copied.files);
+    manifest.files.extend(write_crawler_files(
         &wiki_root,
-        &output_dir.join("pagefind"),
-        &fetch,
-        args.concurrency,
-    )
-    .await?;
+        &output_dir,
+        &content,
+        &sources,
+        &config,
+    )?);
+
+    let stale = previous.stale(&manifest);
+    for path in &stale {
+        remove_output(&output_dir, path);
+    }
+    info!(removed = stale.len(), "removed stale files");
+
+    let search_dir = output_dir.join("pagefind");
+    let downloaded = download_linked_pages(&wiki_root, &content, &search_dir, &fetch).await?;
+    let search = manifest.search_hash(
+        downloaded
+            .iter()
+            .map(|page| (page.link.url.as_str(), page.html.as_str())),
+    );
+    if previous.search.as_ref() == Some(&search) && search_dir.join("pagefind.js").is_file() {
+        info!("search index is up to date");
+    } else {
+        write_pagefind_bundle(&content, downloaded, &search_dir, &config.site).await?;
+    }
+    manifest.search = Some(search);
+    manifest.save(&output_dir)?;
 
     info!(output = %output_dir, "wiki rendered");
 
     Ok(())
 }
 
+/// Loads every markdown file the walker yields, skipping the ones that can't
+/// be loaded with a warning when `lenient` is set.
+fn load_sources(
+    walker: &Walker,
+    options: &MarkdownOptions,
+    cache_dir: &Utf8Path,
+    lenient: bool,
+) -> Result<Vec<Source>> {
+    let root = Utf8Path::from_path(walker.root())
+        .ok_or_else(|| anyhow!("wiki root contains invalid UTF-8"))?;
+    let cache = PageCache::new(&cache_dir.join("pages"), options);
+    let mut sources = Vec::new();
+    for path in walker.markdown_files() {
+        let loaded = path
+            .map_err(anyhow::Error::from)
+            .and_then(|path| cache.load(root, &path));
+        match loaded {
+            Ok(source) => sources.push(source),
+            Err(err) if lenient => warn!(error = %err, "skipping page"),
+            Err(err) => {
+                return Err(err.context(format!("failed to load pages from {}", root)));
+            }
+        }
+    }
+    Ok(sources)
+}
+
 async fn run_index(args: IndexArgs) -> Result<()> {
     let wiki_root = absolute_path(&args.root)?;
     let output_dir = absolute_path(&args.output)?;
-    let fetch = FetchSettings {
-        cache_dir: absolute_path(&args.cache_dir)?,
-        cache_ttl: Duration::from_secs(args.cache_ttl_hours.saturating_mul(60 * 60)),
-        offline: args.offline,
-    };
+    let mut config = Config::load(&wiki_root)?;
+    let fetch = FetchSettings::new(&args.fetch, &config.fetch, &wiki_root)?;
+    config.markdown.cache_dir = Some(fetch.cache_dir.join("diagrams").into_std_path_buf());
+    let content = Content::from_walker(&config.walker(&wiki_root), &config.markdown)
+        .await
+        .with_context(|| format!("failed to load pages from {}", wiki_root))?;
 
-    generate_pagefind_bundle(&wiki_root, &output_dir, &fetch, args.concurrency).await
+    generate_pagefind_bundle(&wiki_root, &content, &output_dir, &fetch, &config.site).await
+}
+
+async fn run_check(args: CheckArgs) -> Result<()> {
+    let wiki_root = absolute_path(&args.root)?;
+    let config = Config::load(&wiki_root)?;
+    let content = Content::from_walker(&config.walker(&wiki_root), &config.markdown)
+        .await
+        .with_context(|| format!("failed to load pages from {}", wiki_root))?;
+    if content.all().is_empty() {
+        bail!("no pages discovered under {}", wiki_root);
+    }
+
+    let report = check::check(&content);
+    match args.format {
+        ReportFormat::Human => report.print_human(),
+        ReportFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).context("failed to encode report")?
+        ),
+    }
+
+    if report.errors > 0 {
+        bail!("wiki check found {} error(s)", report.errors);
+    }
+
+    Ok(())
 }
 
 async fn run_serve(args: ServeArgs) -> Result<()> {
     let site_dir = absolute_path(&args.dir)?;
 
-    if !site_dir.exists() {
-        bail!("site directory does not exist: {}", site_dir);
-    }
+    let reload = if args.watch {
+        let wiki_root = absolute_path(&args.root)?;
+        Some(watch::start(&wiki_root, &site_dir, &args.fetch)?)
+    } else {
+        if !site_dir.exists() {
+            bail!("site directory does not exist: {}", site_dir);
+        }
+        None
+    };
 
     info!(dir = %site_dir, addr = %args.addr, "serving static site");
 
     HttpServer::new({
         let site_dir = site_dir.clone();
         move || {
-            App::new().service(
-                Files::new("/", site_dir.as_str())
-                    .index_file("index.html")
-                    .prefer_utf8(true)
-                    .use_last_modified(true),
-            )
+            App::new()
+                .configure(|config| {
+                    if let Some(reload) = &reload {
+                        watch::configure(config, reload.clone());
+                    }
+                })
+                .service(
+                    Files::new("/", site_dir.as_str())
+                        .index_file("index.html")
+                        .prefer_utf8(true)
+                        .use_last_modified(true)
+                        .default_handler(fn_service({
+                            let not_found = site_dir.join(NOT_FOUND_FILE);
+                            move |request: ServiceRequest| {
+                                serve_not_found(request, not_found.clone())
+                            }
+                        })),
+                )
         }
     })
     .bind(&args.addr)
@@ -279,47 +533,108 @@ async fn run_serve(args: ServeArgs) -> Result<()> {
     Ok(())
 }
 
+/// Responds with the site's 404 page, the plain not found response of the
+/// file service when the site has none.
+async fn serve_not_found(
+    request: ServiceRequest,
+    page: Utf8PathBuf,
+) -> actix_web::Result<ServiceResponse> {
+    let (request, _) = request.into_parts();
+    let response = NamedFile::open_async(page)
+        .await?
+        .customize()
+        .with_status(actix_web::http::StatusCode::NOT_FOUND)
+        .respond_to(&request)
+        .map_into_boxed_body();
+    Ok(ServiceResponse::new(request, response))
+}
+
 async fn generate_pagefind_bundle(
     wiki_root: &Utf8Path,
+    content: &Content,
     output_dir: &Utf8Path,
     fetch: &FetchSettings,
-    concurrency: usize,
+    site: &SiteConfig,
 ) -> Result<()> {
+    let downloaded = download_linked_pages(wiki_root, content, output_dir, fetch).await?;
+    write_pagefind_bundle(content, downloaded, output_dir, site).await
+}
+
+/// Downloads the remote pages the wiki links to, reusing cached copies that
+/// are still fresh.
+async fn download_linked_pages(
+    wiki_root: &Utf8Path,
+    content: &Content,
+    output_dir: &Utf8Path,
+    fetch: &FetchSettings,
+) -> Result<Vec<DownloadedPage>> {
     info!(
         root = %wiki_root,
         output = %output_dir,
         cache = %fetch.cache_dir,
         offline = fetch.offline,
-        "indexing linked pages"
+        "indexing wiki and linked pages"
     );
 
-    prepare_output_dir(output_dir)?;
     fs::create_dir_all(fetch.cache_dir.as_std_path())
         .with_context(|| format!("failed to create cache directory {}", fetch.cache_dir))?;
-
-    let links = collect_links(wiki_root)?;
-    if links.is_empty() {
-        bail!("no links discovered under {}", wiki_root);
+    if content.all().is_empty() {
+        bail!("no pages discovered under {}", wiki_root);
     }
 
+    let links = content.remote_links();
     info!(count = links.len(), "collected unique links");
 
-    let downloaded = download_targets(&links, fetch, concurrency).await?;
-    if downloaded.is_empty() {
-        bail!("no remote pages could be downloaded");
+    let downloaded = if links.is_empty() {
+        Vec::new()
+    } else {
+        download_targets(&links, fetch).await?
+    };
+    if downloaded.len() < links.len() {
+        warn!(
+            downloaded = downloaded.len(),
+            links = links.len(),
+            "some linked pages are missing from the index"
+        );
     }
+    Ok(downloaded)
+}
+
+/// Indexes the wiki pages and the downloaded remote pages into a fresh
+/// Pagefind bundle in `output_dir`.
+async fn write_pagefind_bundle(
+    content: &Content,
+    downloaded: Vec<DownloadedPage>,
+    output_dir: &Utf8Path,
+    site: &SiteConfig,
+) -> Result<()> {
+    prepare_output_dir(output_dir)?;
+    let mut pages: Vec<&Page> = content.all().values().collect();
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
 
     let mut index = PagefindIndex::new(Some(
         PagefindServiceConfig::builder()
             .keep_index_url(true)
-            .force_language("en".into())
+            .force_language(site.language.clone())
             .build(),
     ))?;
 
+    let progress = build_progress_bar(pages.len() as u64);
+    for page in pages {
+        progress.set_message(page.title.clone());
+        let url = page_url(&page.path);
+        let wrapped = wrap_wiki_page(content, page, site);
+        if let Err(err) = index.add_html_file(None, Some(url.clone()), wrapped).await {
+            warn!(error = %err, url = %url, "failed to add page to index");
+        }
+        progress.inc(1);
+    }
+    progress.finish_with_message("Indexed wiki pages");
+
     let progress = build_progress_bar(downloaded.len() as u64);
     for page in downloaded {
         progress.set_message(page.link.title.clone());
-        let wrapped = wrap_remote_content(&page);
+        let wrapped = wrap_remote_content(&page, &site.language);
         if let Err(err) = index
             .add_html_file(None, Some(page.link.url.as_str().into()), wrapped)
             .await
@@ -340,59 +655,115 @@ async fn generate_pagefind_bundle(
     Ok(())
 }
 
-fn render_page(content: &Content, tree: &[Node], page: &Page) -> String {
-    let breadcrumbs = build_breadcrumbs(content, page);
-    let nav_hierarchy: Vec<String> = breadcrumbs.iter().map(|(name, _)| name.clone()).collect();
-    let sidebar = render::content_navigation(tree, &nav_hierarchy);
-    let page_title = format!("{} | Matt's Wiki", page.title);
-
-    let markup = html! {
-        (DOCTYPE)
-        html {
-            (render::header(page_title.as_str()))
-            body {
-                (render::layout(
-                    sidebar,
-                    html! {
-                        ol .hiearchy {
-                            @for (name, link) in &breadcrumbs {
-                                li {
-                                    @if link != "/" {
-                                        span .separator {
-                                            "/"
-                                        }
-                                    }
-                                    a href=(link) {
-                                        (name)
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    html! {
-                        article { (PreEscaped(&page.html)) }
-                    }
-                ))
+/// Renders pages with the site templates and writes them into the output.
+struct PageWriter<'a> {
+    wiki_root: &'a Utf8Path,
+    output_dir: &'a Utf8Path,
+    site: &'a SiteConfig,
+    templates: Templates,
+    images: Option<ImagePipeline>,
+    live_reload: bool,
+}
+
+impl<'a> PageWriter<'a> {
+    /// Loads the templates, with images processed when `images` is set or
+    /// enabled in the config, and the live reload script injected into every
+    /// page when `live_reload` is set.
+    fn new(
+        wiki_root: &'a Utf8Path,
+        output_dir: &'a Utf8Path,
+        config: &'a Config,
+        fetch: &FetchSettings,
+        images: bool,
+        live_reload: bool,
+    ) -> Result<Self> {
+        Ok(PageWriter {
+            wiki_root,
+            output_dir,
+            site: &config.site,
+            templates: Templates::load(wiki_root)?,
+            images: (images || config.images.enabled).then(|| {
+                ImagePipeline::new(
+                    &config.images,
+                    wiki_root,
+                    output_dir,
+                    &fetch.cache_dir.join("images"),
+                )
+            }),
+            live_reload,
+        })
+    }
+
+    /// Reports the build warnings of a page.
+    fn report(&self, page: &Page) {
+        for warning in &page.warnings {
+            warn!(page = %page.path, "{}", warning);
+        }
+        for asset in &page.assets {
+            if !self.wiki_root.join(asset.trim_start_matches('/')).is_file() {
+                warn!(page = %page.path, file = %asset, "referenced file does not exist");
             }
         }
-    };
+    }
 
-    markup.into_string()
-}
+    /// Renders a page and writes it unless the output is the same already,
+    /// reporting its build warnings.
+    fn write(&mut self, content: &Content, tree: &[Node], page: &Page) -> Result<WrittenPage> {
+        self.report(page);
+
+        let processed;
+        let page = match self.images.as_mut() {
+            Some(images) => {
+                let mut copy = page.clone();
+                copy.html = images
+                    .rewrite(&page.html)
+                    .with_context(|| format!("failed to process images of {}", page.path))?;
+                processed = copy;
+                &processed
+            }
+            None => page,
+        };
 
-fn build_breadcrumbs(content: &Content, page: &Page) -> Vec<(String, String)> {
-    let mut trail = Vec::with_capacity(page.parents.len());
-    for parent in &page.parents {
-        if parent == "/" {
-            trail.push(("Wiki".into(), parent.clone()));
-            continue;
+        let context = PageContext::new(content, tree, page, self.site);
+        let mut rendered = self
+            .templates
+            .render_page(&context)
+            .with_context(|| format!("failed to render {}", page.path))?;
+        if self.live_reload {
+            watch::inject_live_reload(&mut rendered);
         }
+        write_page(self.output_dir, page, &rendered)?;
+        Ok(WrittenPage {
+            hash: manifest::hash(rendered.as_bytes()),
+            images: self
+                .images
+                .as_mut()
+                .map(ImagePipeline::take_variants)
+                .unwrap_or_default(),
+        })
+    }
 
-        if let Some(parent_page) = content.get(parent) {
-            trail.push((parent_page.title.clone(), parent.clone()));
+    /// Renders the page served for missing URLs and writes it unless the
+    /// output is the same already, returning its path in the output.
+    fn write_not_found(&self, content: &Content, tree: &[Node]) -> Result<Utf8PathBuf> {
+        let mut rendered = self
+            .templates
+            .render_not_found(content, tree, self.site)
+            .context("failed to render the not found page")?;
+        if self.live_reload {
+            watch::inject_live_reload(&mut rendered);
         }
+        write_if_changed(&self.output_dir.join(NOT_FOUND_FILE), rendered.as_bytes())?;
+        Ok(NOT_FOUND_FILE.into())
     }
-    trail
+}
+
+struct WrittenPage {
+    /// Hash of the rendered page.
+    hash: String,
+
+    /// Image variants the page refers to, relative to the output directory.
+    images: Vec<Utf8PathBuf>,
 }
 
 fn write_page(output_dir: &Utf8Path, page: &Page, contents: &str) -> Result<()> {
@@ -401,10 +772,23 @@ fn write_page(output_dir: &Utf8Path, page: &Page, contents: &str) -> Result<()>
         fs::create_dir_all(parent.as_std_path())
             .with_context(|| format!("failed to create {}", parent))?;
     }
+    write_if_changed(&destination, contents.as_bytes())
+}
 
-    fs::write(destination.as_std_path(), contents)
-        .with_context(|| format!("failed to write {}", destination))?;
-    Ok(())
+/// Removes a file an earlier build wrote at `relative` in the output, if it's
+/// still there, along with the directories it leaves empty.
+fn remove_output(output_dir: &Utf8Path, relative: &Utf8Path) {
+    let path = output_dir.join(relative);
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => warn!(file = %path, error = %err, "failed to remove stale output"),
+    }
+    for dir in relative.ancestors().skip(1) {
+        if dir.as_str().is_empty() || fs::remove_dir(output_dir.join(dir)).is_err() {
+            break;
+        }
+    }
 }
 
 fn page_output_path(output_dir: &Utf8Path, page_path: &str) -> Utf8PathBuf {
@@ -416,167 +800,171 @@ fn page_output_path(output_dir: &Utf8Path, page_path: &str) -> Utf8PathBuf {
     output_dir.join(trimmed).join("index.html")
 }
 
-fn write_assets(output_dir: &Utf8Path) -> Result<()> {
-    let css = grass::from_string(
-        include_str!("../assets/styles.scss").to_owned(),
-        &grass::Options::default().style(OutputStyle::Compressed),
-    )
-    .context("failed to compile styles")?;
-    fs::write(output_dir.join("styles.css").as_std_path(), css.as_bytes())
-        .context("failed to write styles.css")?;
+/// Files of the wiki tree mirrored into the output.
+struct CopiedFiles {
+    /// Paths of all of them, relative to the output directory.
+    files: Vec<Utf8PathBuf>,
 
-    fs::write(
-        output_dir.join("script.js").as_std_path(),
-        include_bytes!("../assets/script.js"),
-    )
-    .context("failed to write script.js")?;
-
-    Ok(())
+    /// How many were copied, the others were up to date.
+    copied: usize,
 }
 
-fn init_tracing() {
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let subscriber = FmtSubscriber::builder()
-        .with_env_filter(env_filter)
-        .finish();
+/// Files of the wiki tree other than markdown that are mirrored into the
+/// output, with their paths relative to the output directory.
+fn wiki_files(walker: &Walker, output_dir: &Utf8Path) -> Result<Vec<(PathBuf, Utf8PathBuf)>> {
+    let mut files = Vec::new();
+    for path in walker.files() {
+        let path = path?;
+        let is_markdown = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+        let is_hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if is_markdown || is_hidden || path.starts_with(output_dir) {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(walker.root()) else {
+            continue;
+        };
+        if relative == Path::new(config::CONFIG_FILE)
+            || relative.starts_with(render::TEMPLATES_DIR)
+            || assets::is_bundled(relative)
+        {
+            continue;
+        }
 
-    let _ = tracing::subscriber::set_global_default(subscriber);
+        let Some(relative) = Utf8Path::from_path(relative) else {
+            warn!(file = %path.display(), "skipping file with a non UTF-8 path");
+            continue;
+        };
+        let relative = relative.to_path_buf();
+        files.push((path, relative));
+    }
+    Ok(files)
 }
 
-fn collect_links(root: &Utf8Path) -> Result<Vec<WikiLink>> {
-    let mut links = Vec::new();
-
-    for entry in WalkDir::new(root).into_iter().filter_entry(should_visit) {
-        let entry = entry?;
-        if !entry.file_type().is_file() || !is_markdown_file(&entry) {
+/// Copies the files of the wiki tree other than markdown into the output, at
+/// the root-relative paths pages refer to them by.
+fn copy_files(walker: &Walker, output_dir: &Utf8Path) -> Result<CopiedFiles> {
+    let mut files = Vec::new();
+    let mut copied = 0;
+    for (path, relative) in wiki_files(walker, output_dir)? {
+        let destination = output_dir.as_std_path().join(&relative);
+        files.push(relative);
+        if is_up_to_date(&path, &destination) {
             continue;
         }
-
-        let full_path = Utf8PathBuf::from_path_buf(entry.path().to_path_buf())
-            .map_err(|_| anyhow!("paths must be valid utf-8"))?;
-        let relative = full_path
-            .strip_prefix(root)
-            .with_context(|| format!("{} is not inside {}", full_path, root))?;
-
-        let tags = derive_tags(relative);
-        let contents = fs::read_to_string(full_path.as_std_path())
-            .with_context(|| format!("failed to read {}", full_path))?;
-        let page_links = parse_links(&contents, &tags, relative);
-        links.extend(page_links);
-    }
-
-    Ok(dedupe_links(links))
-}
-
-fn parse_links(contents: &str, tags: &[String], source: &Utf8Path) -> Vec<WikiLink> {
-    let mut found = Vec::new();
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-
-    let mut current_link: Option<Url> = None;
-    let mut link_text = String::new();
-    let mut strong_depth = 0usize;
-    let mut starred = false;
-
-    let parser = MarkdownParser::new_ext(contents, options);
-    for event in parser {
-        match event {
-            Event::Start(Tag::Strong) => strong_depth += 1,
-            Event::End(TagEnd::Strong) => strong_depth = strong_depth.saturating_sub(1),
-            Event::Start(Tag::Link { dest_url, .. }) => {
-                if let Ok(url) = Url::parse(&dest_url) {
-                    if matches!(url.scheme(), "http" | "https") {
-                        starred = strong_depth > 0;
-                        link_text.clear();
-                        current_link = Some(url);
-                    }
-                }
-            }
-            Event::Text(text) => {
-                if current_link.is_some() {
-                    link_text.push_str(&text);
-                }
-            }
-            Event::End(TagEnd::Link) => {
-                if let Some(url) = current_link.take() {
-                    let title = if link_text.trim().is_empty() {
-                        url.domain()
-                            .map(|d| d.into())
-                            .unwrap_or_else(|| url.as_str().into())
-                    } else {
-                        link_text.trim().into()
-                    };
-                    found.push(WikiLink {
-                        title,
-                        url,
-                        tags: tags.to_vec(),
-                        source_path: source.to_owned(),
-                        starred,
-                    });
-                    link_text.clear();
-                    starred = false;
-                }
-            }
-            _ => {}
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
         }
+        fs::copy(&path, &destination)
+            .with_context(|| format!("failed to copy {}", path.display()))?;
+        copied += 1;
     }
+    Ok(CopiedFiles { files, copied })
+}
 
-    found
+fn write_assets(
+    wiki_root: &Utf8Path,
+    output_dir: &Utf8Path,
+    highlight: &HighlightConfig,
+) -> Result<Vec<Utf8PathBuf>> {
+    let css = assets::compile_styles(wiki_root)?;
+    write_if_changed(&output_dir.join("styles.css"), css.as_bytes())?;
+
+    let js = assets::bundle_scripts(wiki_root)?;
+    write_if_changed(&output_dir.join("script.js"), js.as_bytes())?;
+
+    let theme_css = |name: &str| {
+        mwp_content::theme_css(name).ok_or_else(|| {
+            anyhow!(
+                "unknown highlight theme {:?}, available themes: {}",
+                name,
+                mwp_content::theme_names().join(", ")
+            )
+        })
+    };
+    let highlight_css = format!(
+        "{}\n@media (prefers-color-scheme: dark) {{\n{}}}\n",
+        theme_css(&highlight.theme)?,
+        theme_css(&highlight.dark_theme)?
+    );
+    write_if_changed(&output_dir.join("highlight.css"), highlight_css.as_bytes())?;
+
+    Ok(ASSET_FILES.map(Utf8PathBuf::from).to_vec())
 }
 
-fn dedupe_links(links: Vec<WikiLink>) -> Vec<WikiLink> {
-    let mut map = HashMap::new();
-    for link in links {
-        map.entry(link.url.clone()).or_insert(link);
+/// Writes `sitemap.xml` when the base URL is known, and `robots.txt` unless
+/// the wiki has its own, returning their paths in the output.
+fn write_crawler_files(
+    wiki_root: &Utf8Path,
+    output_dir: &Utf8Path,
+    content: &Content,
+    sources: &[Source],
+    config: &Config,
+) -> Result<Vec<Utf8PathBuf>> {
+    let mut written = Vec::new();
+
+    let commits = sitemap::git_dates(wiki_root);
+    let dates: HashMap<String, String> = sources
+        .iter()
+        .filter_map(|source| Some((source.page.path.clone(), commits.get(&source.path)?.clone())))
+        .collect();
+    match sitemap::sitemap(content, &config.site, &dates) {
+        Some(xml) => {
+            write_if_changed(&output_dir.join(sitemap::SITEMAP_FILE), xml.as_bytes())?;
+            written.push(sitemap::SITEMAP_FILE.into());
+        }
+        None => info!("no base_url configured, skipping sitemap.xml"),
+    }
+
+    if !wiki_root.join(sitemap::ROBOTS_FILE).is_file() {
+        let robots = sitemap::robots(&config.robots, &config.site);
+        write_if_changed(&output_dir.join(sitemap::ROBOTS_FILE), robots.as_bytes())?;
+        written.push(sitemap::ROBOTS_FILE.into());
     }
-    map.into_values().collect()
+
+    Ok(written)
 }
 
-fn derive_tags(relative: &Utf8Path) -> Vec<String> {
-    let mut dir = relative.to_owned();
-    let mut tags = Vec::new();
-    if dir.file_name().is_some() {
-        dir.pop();
+/// Paths of the crawler files a build writes, see [`write_crawler_files`].
+fn crawler_files(wiki_root: &Utf8Path, site: &SiteConfig) -> Vec<Utf8PathBuf> {
+    let mut files = Vec::new();
+    if site.base_url.is_some() {
+        files.push(sitemap::SITEMAP_FILE.into());
     }
-    tags.extend(
-        dir.components()
-            .filter_map(|component| match component.as_str() {
-                "" => None,
-                name if name.eq_ignore_ascii_case("index") => None,
-                name => Some(name.replace('_', " ")),
-            }),
-    );
-    if tags.is_empty() {
-        if let Some(stem) = relative.file_stem() {
-            tags.push(stem.replace('_', " "));
-        }
+    if !wiki_root.join(sitemap::ROBOTS_FILE).is_file() {
+        files.push(sitemap::ROBOTS_FILE.into());
     }
-    tags
+    files
 }
 
-fn should_visit(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|name| !IGNORED_DIRECTORIES.contains(&name))
-        .unwrap_or(true)
+/// Keeps the panic hook from printing the diagram renderer panics that
+/// mwp-content catches, they're reported as page warnings.
+fn quiet_diagram_panics() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if !mwp_content::rendering_diagram() {
+            default(info);
+        }
+    }));
 }
 
-fn is_markdown_file(entry: &DirEntry) -> bool {
-    entry
-        .path()
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+fn init_tracing() {
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(env_filter)
+        .finish();
+
+    let _ = tracing::subscriber::set_global_default(subscriber);
 }
 
-async fn download_targets(
-    links: &[WikiLink],
-    fetch: &FetchSettings,
-    concurrency: usize,
-) -> Result<Vec<DownloadedPage>> {
+async fn download_targets(links: &[Link], fetch: &FetchSettings) -> Result<Vec<DownloadedPage>> {
     let client = Client::builder()
-        .user_agent("mwp-indexer/0.2 (+https://github.com/matoous/mwp)")
+        .user_agent(fetch.user_agent.as_str())
         .connect_timeout(Duration::from_secs(10))
         .timeout(Duration::from_secs(30))
         .tcp_keepalive(Duration::from_secs(30))
@@ -594,7 +982,7 @@ async fn download_targets(
         let fetch = fetch.clone();
         async move { fetch_page(&client, &fetch, link).await }
     }))
-    .buffer_unordered(concurrency.max(1));
+    .buffer_unordered(fetch.concurrency.max(1));
 
     tokio::pin!(stream);
 
@@ -613,11 +1001,7 @@ async fn download_targets(
     Ok(results)
 }
 
-async fn fetch_page(
-    client: &Client,
-    fetch: &FetchSettings,
-    link: WikiLink,
-) -> Result<FetchOutcome> {
+async fn fetch_page(client: &Client, fetch: &FetchSettings, link: Link) -> Result<FetchOutcome> {
     let cache_paths = cache_paths(&fetch.cache_dir, &link.url);
     let cached = match read_cache(&cache_paths, &link) {
         Ok(cached) => cached,
@@ -641,10 +1025,10 @@ async fn fetch_page(
         };
     }
 
-    if let Some((entry, page)) = &cached {
-        if !is_cache_stale(entry, fetch.cache_ttl) {
-            return Ok(FetchOutcome::Cached(page.clone()));
-        }
+    if let Some((entry, page)) = &cached
+        && !is_cache_stale(entry, fetch.cache_ttl)
+    {
+        return Ok(FetchOutcome::Cached(page.clone()));
     }
 
     let request_meta = cached.as_ref().map(|(entry, _)| entry);
@@ -675,7 +1059,7 @@ async fn fetch_page(
 
 async fn fetch_remote_page(
     client: &Client,
-    link: &WikiLink,
+    link: &Link,
     cache_entry: Option<&CacheEntry>,
 ) -> Result<FetchOutcome> {
     for attempt in 1..=MAX_FETCH_ATTEMPTS {
@@ -686,15 +1070,15 @@ async fn fetch_remote_page(
             .header("Cache-Control", "max-age=0");
 
         if let Some(entry) = cache_entry {
-            if let Some(etag) = &entry.etag {
-                if let Ok(value) = HeaderValue::from_str(etag) {
-                    request = request.header(IF_NONE_MATCH, value);
-                }
+            if let Some(etag) = &entry.etag
+                && let Ok(value) = HeaderValue::from_str(etag)
+            {
+                request = request.header(IF_NONE_MATCH, value);
             }
-            if let Some(last_modified) = &entry.last_modified {
-                if let Ok(value) = HeaderValue::from_str(last_modified) {
-                    request = request.header(IF_MODIFIED_SINCE, value);
-                }
+            if let Some(last_modified) = &entry.last_modified
+                && let Ok(value) = HeaderValue::from_str(last_modified)
+            {
+                request = request.header(IF_MODIFIED_SINCE, value);
             }
         }
 
@@ -794,7 +1178,7 @@ fn cache_key(url: &Url) -> String {
     format!("{:x}", hasher.finalize())
 }
 
-fn read_cache(paths: &CachePaths, link: &WikiLink) -> Result<Option<(CacheEntry, DownloadedPage)>> {
+fn read_cache(paths: &CachePaths, link: &Link) -> Result<Option<(CacheEntry, DownloadedPage)>> {
     if !paths.meta.exists() || !paths.body.exists() {
         return Ok(None);
     }
@@ -896,7 +1280,7 @@ fn build_progress_bar(len: u64) -> ProgressBar {
     pb
 }
 
-fn wrap_remote_content(page: &DownloadedPage) -> String {
+fn wrap_remote_content(page: &DownloadedPage, language: &str) -> String {
     let parsed = Html::parse_document(&page.html);
     let title_selector = Selector::parse("title").expect("valid title selector");
     let body_selector = Selector::parse("body").expect("valid body selector");
@@ -917,15 +1301,19 @@ fn wrap_remote_content(page: &DownloadedPage) -> String {
         .unwrap_or_else(|| page.link.title.clone());
 
     let mut document = String::new();
-    document.push_str("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\" />");
+    document.push_str(&format!(
+        "<!DOCTYPE html><html lang=\"{}\"><head><meta charset=\"utf-8\" />",
+        encode_safe(language)
+    ));
     document.push_str(&format!(
         "<meta data-pagefind-meta=\"title\" content=\"{}\" />",
         encode_safe(&title)
     ));
     document.push_str(&format!(
         "<meta data-pagefind-meta=\"source\" content=\"{}\" />",
-        encode_safe(page.link.source_path.as_str())
+        encode_safe(&page.link.source)
     ));
+    document.push_str("<meta data-pagefind-filter=\"kind\" content=\"external\" />");
     if page.link.starred {
         document.push_str("<meta data-pagefind-filter=\"starred\" content=\"true\" />");
     }
@@ -941,3 +1329,104 @@ fn wrap_remote_content(page: &DownloadedPage) -> String {
 
     document
 }
+
+/// Search index document for a wiki page, with its breadcrumbs and tags as
+/// metadata and the page body as the indexed content.
+fn wrap_wiki_page(content: &Content, page: &Page, site: &SiteConfig) -> String {
+    let breadcrumbs: Vec<String> = render::breadcrumbs(content, page, &site.root_label)
+        .into_iter()
+        .map(|crumb| crumb.title)
+        .collect();
+
+    let mut document = String::new();
+    document.push_str(&format!(
+        "<!DOCTYPE html><html lang=\"{}\"><head><meta charset=\"utf-8\" />",
+        encode_safe(&site.language)
+    ));
+    document.push_str(&format!(
+        "<meta data-pagefind-meta=\"title\" content=\"{}\" />",
+        encode_safe(&page.title)
+    ));
+    document.push_str(&format!(
+        "<meta data-pagefind-meta=\"breadcrumbs\" content=\"{}\" />",
+        encode_safe(&breadcrumbs.join(" / "))
+    ));
+    if !page.tags.is_empty() {
+        document.push_str(&format!(
+            "<meta data-pagefind-meta=\"tags\" content=\"{}\" />",
+            encode_safe(&page.tags.join(", "))
+        ));
+    }
+    document.push_str("<meta data-pagefind-filter=\"kind\" content=\"wiki\" />");
+    for tag in &page.tags {
+        document.push_str(&format!(
+            "<meta data-pagefind-filter=\"tag\" content=\"{}\" />",
+            encode_safe(tag)
+        ));
+    }
+    document.push_str("</head><body data-pagefind-body><article>");
+    document.push_str(&page.html);
+    document.push_str("</article></body></html>");
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn build(root: &Utf8Path, flags: &[&str]) {
+        let (wiki, dist, cache) = (root.join("wiki"), root.join("dist"), root.join("cache"));
+        let args = [
+            "mwp",
+            "build",
+            "--root",
+            wiki.as_str(),
+            "--output",
+            dist.as_str(),
+            "--cache-dir",
+            cache.as_str(),
+            "--offline",
+        ];
+        let cli = Cli::try_parse_from(args.iter().chain(flags)).unwrap();
+        let CommandKind::Build(args) = cli.command else {
+            unreachable!("parsed a build command");
+        };
+        run_build(args).await.unwrap();
+    }
+
+    fn snapshot(dir: &Utf8Path) -> Vec<(Utf8PathBuf, Vec<u8>)> {
+        files::list_files(dir)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let contents = fs::read(&path).unwrap();
+                (path, contents)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn dry_run_writes_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        let (wiki, dist) = (root.join("wiki"), root.join("dist"));
+        fs::create_dir_all(&wiki).unwrap();
+        fs::write(wiki.join("index.md"), "# Home\n").unwrap();
+        fs::write(wiki.join("old.md"), "# Old\n").unwrap();
+
+        build(root, &[]).await;
+        assert!(dist.join("old/index.html").is_file());
+
+        fs::remove_file(wiki.join("old.md")).unwrap();
+        fs::write(wiki.join("new.md"), "# New\n").unwrap();
+        let before = snapshot(&dist);
+        build(root, &["--dry-run"]).await;
+        build(root, &["--clean", "--dry-run"]).await;
+        assert_eq!(snapshot(&dist), before);
+
+        build(root, &[]).await;
+        assert!(!dist.join("old/index.html").exists());
+        assert!(dist.join("new/index.html").is_file());
+    }
+}
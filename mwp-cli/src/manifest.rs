@@ -0,0 +1,539 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+use mwp_content::{Content, MarkdownOptions, Node, Page, load_page};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use crate::{
+    config::{CONFIG_FILE, Config},
+    files::list_files,
+    render,
+};
+
+/// File in the output directory that records what the last build wrote.
+pub const MANIFEST_FILE: &str = ".mwp-manifest.json";
+
+/// Bumped whenever the manifest or the cached pages change shape.
+const VERSION: u32 = 1;
+
+/// What the last build wrote into the output directory and what it was
+/// built from, so the next build only redoes what changed since.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+
+    /// Hash of the config, templates and build flags every page depends on.
+    pub settings: String,
+
+    /// Hash of the navigation tree shown on every page.
+    pub nav: String,
+
+    /// Hash of the wiki pages and remote pages in the search bundle.
+    pub search: Option<String>,
+
+    /// Written pages by their path.
+    pub pages: BTreeMap<String, PageRecord>,
+
+    /// Other written files, relative to the output directory: the bundled
+    /// assets and the files copied from the wiki. The search bundle isn't
+    /// tracked since it's regenerated as a whole.
+    #[serde(default)]
+    pub files: BTreeSet<Utf8PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageRecord {
+    pub inputs: PageInputs,
+
+    /// Written file, relative to the output directory.
+    pub output: Utf8PathBuf,
+
+    /// Hash of the written file.
+    pub output_hash: String,
+
+    /// Image variants the page refers to, relative to the output directory.
+    #[serde(default)]
+    pub images: Vec<Utf8PathBuf>,
+}
+
+/// Everything other than the settings and the navigation tree the output of
+/// a page depends on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageInputs {
+    /// Source file, relative to the wiki root.
+    pub source: Utf8PathBuf,
+
+    pub source_hash: String,
+
+    /// Source hashes of the pages embedded into this one, directly or
+    /// through other embeds.
+    pub embeds: BTreeMap<String, String>,
+
+    /// Pages the page links to, links change once their target moves.
+    pub links: Vec<String>,
+
+    /// Pages linking to this one, listed at its bottom.
+    pub backlinks: Vec<String>,
+
+    /// Hashes of the local files the page refers to, only tracked when images
+    /// are processed since the output doesn't depend on them otherwise.
+    pub assets: BTreeMap<String, Option<String>>,
+}
+
+impl Manifest {
+    pub fn new(settings: String, nav: String) -> Self {
+        Manifest {
+            version: VERSION,
+            settings,
+            nav,
+            search: None,
+            pages: BTreeMap::new(),
+            files: BTreeSet::new(),
+        }
+    }
+
+    /// Loads the manifest of the last build into `output_dir`, an empty one
+    /// when there's none or it can't be read.
+    pub fn load(output_dir: &Utf8Path) -> Self {
+        let path = output_dir.join(MANIFEST_FILE);
+        let Ok(raw) = fs::read(&path) else {
+            return Manifest::default();
+        };
+        match serde_json::from_slice::<Manifest>(&raw) {
+            Ok(manifest) if manifest.version == VERSION => manifest,
+            Ok(_) => Manifest::default(),
+            Err(err) => {
+                warn!(file = %path, error = %err, "ignoring invalid build manifest");
+                Manifest::default()
+            }
+        }
+    }
+
+    pub fn save(&self, output_dir: &Utf8Path) -> Result<()> {
+        let path = output_dir.join(MANIFEST_FILE);
+        let raw = serde_json::to_vec_pretty(self).context("failed to encode build manifest")?;
+        fs::write(&path, raw).with_context(|| format!("failed to write {}", path))
+    }
+
+    /// Record of a page the last build can be kept from: it was built with
+    /// the same settings and navigation as the `next` build from the same
+    /// inputs, and its output wasn't changed or removed since.
+    pub fn unchanged(
+        &self,
+        next: &Manifest,
+        output_dir: &Utf8Path,
+        path: &str,
+        inputs: &PageInputs,
+    ) -> Option<&PageRecord> {
+        let record = self.pages.get(path)?;
+        let fresh = self.settings == next.settings
+            && self.nav == next.nav
+            && record.inputs == *inputs
+            && fs::read(output_dir.join(&record.output))
+                .is_ok_and(|output| hash(&output) == record.output_hash);
+        fresh.then_some(record)
+    }
+
+    /// Files this build wrote that the `next` one doesn't, left behind by
+    /// removed pages and files.
+    pub fn stale(&self, next: &Manifest) -> Vec<Utf8PathBuf> {
+        let current = next.outputs();
+        self.outputs()
+            .into_iter()
+            .filter(|path| !current.contains(path))
+            .map(Utf8Path::to_path_buf)
+            .collect()
+    }
+
+    fn outputs(&self) -> BTreeSet<&Utf8Path> {
+        self.pages
+            .values()
+            .flat_map(|record| std::iter::once(&record.output).chain(&record.images))
+            .chain(&self.files)
+            .map(Utf8PathBuf::as_path)
+            .collect()
+    }
+
+    /// Hash of everything the search bundle is built from, the recorded
+    /// pages and the remote pages given as `(url, html)` pairs.
+    pub fn search_hash<'a>(&self, remote: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(&self.settings);
+        hasher.update(&self.nav);
+        for (path, record) in &self.pages {
+            hasher.update(path);
+            hasher.update(serde_json::to_vec(&record.inputs).unwrap_or_default());
+        }
+        let mut remote: Vec<(&str, &str)> = remote.into_iter().collect();
+        remote.sort_unstable();
+        for (url, html) in remote {
+            hasher.update(url);
+            hasher.update(hash(html.as_bytes()));
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// A parsed markdown file of the wiki.
+pub struct Source {
+    /// Path of the file, relative to the wiki root.
+    pub path: Utf8PathBuf,
+
+    /// Hash of the file contents.
+    pub hash: String,
+
+    pub page: Page,
+}
+
+/// Parses markdown files, reusing the pages earlier builds parsed from
+/// identical files with the same options.
+pub struct PageCache<'a> {
+    dir: Utf8PathBuf,
+    options: &'a MarkdownOptions,
+    options_hash: String,
+}
+
+impl<'a> PageCache<'a> {
+    pub fn new(cache_dir: &Utf8Path, options: &'a MarkdownOptions) -> Self {
+        PageCache {
+            dir: cache_dir.to_path_buf(),
+            options,
+            options_hash: hash(&serde_json::to_vec(options).unwrap_or_default()),
+        }
+    }
+
+    /// Loads the markdown file at `path`, which must be under `root`.
+    pub fn load(&self, root: &Utf8Path, path: &Path) -> Result<Source> {
+        let relative = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(|relative| Utf8Path::from_path(relative))
+            .ok_or_else(|| anyhow!("invalid page path {}", path.display()))?
+            .to_path_buf();
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let source_hash = hash(&bytes);
+
+        let mut hasher = Sha1::new();
+        hasher.update(VERSION.to_le_bytes());
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(&self.options_hash);
+        hasher.update(relative.as_str());
+        hasher.update(&source_hash);
+        let cached = self.dir.join(format!("{:x}.json", hasher.finalize()));
+
+        if let Some(page) = fs::read(&cached)
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+        {
+            return Ok(Source {
+                path: relative,
+                hash: source_hash,
+                page,
+            });
+        }
+
+        let page = load_page(root.as_std_path(), path, self.options)?;
+        let stored = fs::create_dir_all(&self.dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(fs::write(&cached, serde_json::to_vec(&page)?)?));
+        if let Err(err) = stored {
+            warn!(file = %cached, error = %err, "failed to cache parsed page");
+        }
+        Ok(Source {
+            path: relative,
+            hash: source_hash,
+            page,
+        })
+    }
+}
+
+/// Collects the inputs of pages once they're linked together into content.
+pub struct Dependencies<'a> {
+    content: &'a Content,
+    wiki_root: &'a Utf8Path,
+    sources: HashMap<&'a str, (&'a Utf8Path, &'a str)>,
+    assets: Option<HashMap<String, Option<String>>>,
+}
+
+impl<'a> Dependencies<'a> {
+    /// Tracks the files pages refer to only when `assets` is set.
+    pub fn new(
+        content: &'a Content,
+        wiki_root: &'a Utf8Path,
+        sources: &'a [Source],
+        assets: bool,
+    ) -> Self {
+        Dependencies {
+            content,
+            wiki_root,
+            sources: sources
+                .iter()
+                .map(|source| {
+                    (
+                        source.page.path.as_str(),
+                        (source.path.as_path(), source.hash.as_str()),
+                    )
+                })
+                .collect(),
+            assets: assets.then(HashMap::new),
+        }
+    }
+
+    pub fn inputs(&mut self, page: &Page) -> PageInputs {
+        let (source, source_hash) = self
+            .sources
+            .get(page.path.as_str())
+            .copied()
+            .unwrap_or((Utf8Path::new(""), ""));
+
+        let mut embeds = BTreeMap::new();
+        let mut pending: Vec<&Page> = vec![page];
+        while let Some(current) = pending.pop() {
+            for target in current
+                .embeds
+                .iter()
+                .filter_map(|embed| embed.resolved.as_ref())
+            {
+                if target == &page.path || embeds.contains_key(target) {
+                    continue;
+                }
+                let hash = self.sources.get(target.as_str()).map(|(_, hash)| *hash);
+                embeds.insert(target.clone(), hash.unwrap_or_default().to_string());
+                pending.extend(self.content.get(target));
+            }
+        }
+
+        let mut links: Vec<String> = page.linked_pages().map(str::to_string).collect();
+        links.sort_unstable();
+        links.dedup();
+
+        let mut backlinks: Vec<String> = self
+            .content
+            .backlinks(&page.path)
+            .into_iter()
+            .map(|page| page.path.clone())
+            .collect();
+        backlinks.sort_unstable();
+
+        let mut assets = BTreeMap::new();
+        if let Some(hashes) = self.assets.as_mut() {
+            for asset in &page.assets {
+                let hash = hashes.entry(asset.clone()).or_insert_with(|| {
+                    fs::read(self.wiki_root.join(asset.trim_start_matches('/')))
+                        .ok()
+                        .map(|bytes| hash(&bytes))
+                });
+                assets.insert(asset.clone(), hash.clone());
+            }
+        }
+
+        PageInputs {
+            source: source.to_path_buf(),
+            source_hash: source_hash.to_string(),
+            embeds,
+            links,
+            backlinks,
+            assets,
+        }
+    }
+}
+
+/// Removes everything from the output directory of an earlier build, or
+/// lists what would be removed when `dry_run` is set.
+///
+/// Refuses to touch a directory that contains the wiki, or that has files
+/// but no manifest, since it's likely not a build output at all.
+pub fn clean_output_dir(output_dir: &Utf8Path, wiki_root: &Utf8Path, dry_run: bool) -> Result<()> {
+    if !output_dir.exists() {
+        return Ok(());
+    }
+    if wiki_root.starts_with(output_dir) {
+        bail!("refusing to clean {}, it contains the wiki", output_dir);
+    }
+
+    let mut entries = Vec::new();
+    for entry in output_dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {}", output_dir))?
+    {
+        entries.push(entry?.into_path());
+    }
+    if !entries.is_empty() && !output_dir.join(MANIFEST_FILE).is_file() {
+        bail!(
+            "refusing to clean {}, it has no {} and doesn't look like the output of an earlier build",
+            output_dir,
+            MANIFEST_FILE
+        );
+    }
+
+    for entry in entries {
+        if dry_run {
+            let files = if entry.is_dir() {
+                list_files(&entry)?
+            } else {
+                vec![entry]
+            };
+            for file in files {
+                println!("would remove {}", file);
+            }
+            continue;
+        }
+
+        let removed = if entry.is_dir() {
+            fs::remove_dir_all(&entry)
+        } else {
+            fs::remove_file(&entry)
+        };
+        removed.with_context(|| format!("failed to remove {}", entry))?;
+    }
+    Ok(())
+}
+
+/// Hash of the config file, the templates and the flags every page of a
+/// build depends on.
+pub fn settings_hash(wiki_root: &Utf8Path, config: &Config, images: bool) -> Result<String> {
+    let mut hasher = Sha1::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    if let Ok(raw) = fs::read(wiki_root.join(CONFIG_FILE)) {
+        hasher.update(raw);
+    }
+    hasher.update(serde_json::to_vec(&config.site)?);
+    hasher.update([u8::from(images || config.images.enabled)]);
+
+    let dir = wiki_root.join(render::TEMPLATES_DIR);
+    if dir.is_dir() {
+        let mut templates = list_files(&dir)?;
+        templates.sort();
+        for template in templates {
+            hasher.update(template.as_str());
+            hasher.update(
+                fs::read(&template).with_context(|| format!("failed to read {}", template))?,
+            );
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash of the navigation tree.
+pub fn nav_hash(tree: &[Node]) -> String {
+    fn update(hasher: &mut Sha1, nodes: &[Node]) {
+        for node in nodes {
+            hasher.update(&node.name);
+            hasher.update([0]);
+            hasher.update(&node.path);
+            hasher.update([1]);
+            update(hasher, &node.children);
+            hasher.update([2]);
+        }
+    }
+
+    let mut hasher = Sha1::new();
+    update(&mut hasher, tree);
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_and_unchanged_pages() {
+        let pages = [
+            ("/git", "# Git\n\nSee [[Tools]].\n"),
+            ("/tools", "# Tools\n"),
+            ("/summary", "# Summary\n\n![[Git]]\n"),
+            ("/overview", "# Overview\n\n![[Summary]]\n"),
+        ];
+        let sources: Vec<Source> = pages
+            .iter()
+            .map(|(path, markdown)| Source {
+                path: format!("{}.md", path.trim_start_matches('/')).into(),
+                hash: hash(markdown.as_bytes()),
+                page: Page::from(path, markdown.to_string()).unwrap(),
+            })
+            .collect();
+        let content = Content::from_pages(sources.iter().map(|source| source.page.clone()));
+        let mut dependencies = Dependencies::new(&content, Utf8Path::new("/wiki"), &sources, false);
+
+        let overview = dependencies.inputs(content.get("/overview").unwrap());
+        assert_eq!(
+            overview.embeds.keys().collect::<Vec<_>>(),
+            ["/git", "/summary"]
+        );
+        assert_eq!(overview.embeds["/git"], sources[0].hash);
+        let tools = dependencies.inputs(content.get("/tools").unwrap());
+        assert_eq!(tools.backlinks, ["/git"]);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let output_dir = Utf8Path::from_path(tmp.path()).unwrap();
+        fs::create_dir_all(output_dir.join("tools")).unwrap();
+        fs::write(output_dir.join("tools/index.html"), "<p>Tools</p>").unwrap();
+        let mut manifest = Manifest::new("settings".into(), "nav".into());
+        manifest.pages.insert(
+            "/tools".into(),
+            PageRecord {
+                inputs: tools.clone(),
+                output: "tools/index.html".into(),
+                output_hash: hash(b"<p>Tools</p>"),
+                images: vec!["img/tools.480w.webp".into()],
+            },
+        );
+        manifest.save(output_dir).unwrap();
+        let previous = Manifest::load(output_dir);
+
+        let next = Manifest::new("settings".into(), "nav".into());
+        assert!(
+            previous
+                .unchanged(&next, output_dir, "/tools", &tools)
+                .is_some()
+        );
+        let renamed = Manifest::new("settings".into(), "other nav".into());
+        assert!(
+            previous
+                .unchanged(&renamed, output_dir, "/tools", &tools)
+                .is_none()
+        );
+        let mut moved = Manifest::new("settings".into(), "nav".into());
+        moved.files.insert("styles.css".into());
+        assert_eq!(
+            previous.stale(&moved),
+            ["img/tools.480w.webp", "tools/index.html"]
+        );
+        fs::write(output_dir.join("tools/index.html"), "<p>Edited</p>").unwrap();
+        assert!(
+            previous
+                .unchanged(&next, output_dir, "/tools", &tools)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn clean_only_build_outputs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        let (wiki, dist) = (root.join("wiki"), root.join("dist"));
+        fs::create_dir_all(dist.join("tools")).unwrap();
+        fs::create_dir_all(&wiki).unwrap();
+        fs::write(dist.join("tools/index.html"), "").unwrap();
+
+        assert!(clean_output_dir(&dist, &wiki, false).is_err());
+        assert!(clean_output_dir(root, &wiki, false).is_err());
+
+        fs::write(dist.join(MANIFEST_FILE), "{}").unwrap();
+        assert!(clean_output_dir(&dist, &wiki, true).is_ok());
+        assert!(dist.join("tools/index.html").is_file());
+        assert!(clean_output_dir(&dist, &wiki, false).is_ok());
+        assert_eq!(dist.read_dir().unwrap().count(), 0);
+    }
+}
@@ -1,92 +1,256 @@
-use maud::{Markup, PreEscaped, html};
-use mwp_content::Node;
-
-const EXPAND_ICON: &str = include_str!("static/expand.svg");
-const BURGER_ICON: &str = include_str!("static/burger.svg");
-
-pub fn header(page_title: &str) -> Markup {
-    html! {
-        meta charset="utf-8";
-        meta content="width=device-width,minimum-scale=1" name="viewport";
-        title { (page_title) }
-        link href="/styles.css" rel="stylesheet";
-        link href="/pagefind/pagefind-ui.css" rel="stylesheet";
-        script src="/pagefind/pagefind-ui.js" {}
-        script type="text/javascript" defer="" src="/script.js" {}
-    }
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use html_escape::encode_quoted_attribute;
+use minijinja::{AutoEscape, Environment, escape_formatter};
+use mwp_content::{Content, Heading, Node, Page, page_url};
+use serde::Serialize;
+
+use crate::{config::SiteConfig, files::list_files};
+
+/// Directory in the wiki root whose files override the built-in templates.
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// Path of the page shown for missing URLs.
+const NOT_FOUND_PATH: &str = "/404";
+
+const NOT_FOUND_MARKDOWN: &str = "# Page not found\n\n\
+    There's no page at this address. Try searching, or start from the [front page](/).\n";
+
+/// Built-in templates by name, the defaults for anything not overridden.
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("page.html", include_str!("../templates/page.html")),
+    (
+        "partials/head.html",
+        include_str!("../templates/partials/head.html"),
+    ),
+    (
+        "partials/nav.html",
+        include_str!("../templates/partials/nav.html"),
+    ),
+    (
+        "partials/sidebar.html",
+        include_str!("../templates/partials/sidebar.html"),
+    ),
+    (
+        "partials/breadcrumbs.html",
+        include_str!("../templates/partials/breadcrumbs.html"),
+    ),
+    (
+        "partials/backlinks.html",
+        include_str!("../templates/partials/backlinks.html"),
+    ),
+    (
+        "partials/toc.html",
+        include_str!("../templates/partials/toc.html"),
+    ),
+    (
+        "partials/footer.html",
+        include_str!("../templates/partials/footer.html"),
+    ),
+    (
+        "partials/search.html",
+        include_str!("../templates/partials/search.html"),
+    ),
+    (
+        "icons/burger.svg",
+        include_str!("../templates/icons/burger.svg"),
+    ),
+    (
+        "icons/expand.svg",
+        include_str!("../templates/icons/expand.svg"),
+    ),
+];
+
+/// Page templates, the built-in ones with any overrides from the wiki.
+pub struct Templates {
+    env: Environment<'static>,
 }
 
-fn footer() -> Markup {
-    html! {
-        footer {
-            div {
-                "© Matous Dzivjak, 2024 · "
-                a href="https://dzx.cz" {
-                    "dzx.cz"
-                }
+impl Templates {
+    /// Loads the built-in templates, replacing each one that has a file with
+    /// the same relative path under the `templates/` directory of the wiki.
+    /// Other files in that directory are added as extra templates.
+    pub fn load(wiki_root: &Utf8Path) -> Result<Self> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        // The default escaping also turns `/` into `&#x2f;`, which mangles every URL.
+        env.set_formatter(|out, state, value| match value.as_str() {
+            Some(text) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => {
+                Ok(out.write_str(&encode_quoted_attribute(text))?)
             }
-            div {
-                "Software Engineer @ SumUp"
-            }
-            address {
-                "Berlin, Germany · Litomerice, Czech Republic"
-            }
-            div {
-                "matousdzivjak@gmail.com · GitHub · Keybase · LinkedIn · Instagram"
+            _ => escape_formatter(out, state, value),
+        });
+        for (name, source) in BUILTIN_TEMPLATES {
+            env.add_template(name, source)
+                .with_context(|| format!("invalid built-in template {name}"))?;
+        }
+
+        let dir = wiki_root.join(TEMPLATES_DIR);
+        if dir.is_dir() {
+            for entry in list_files(&dir)? {
+                let name = entry
+                    .strip_prefix(&dir)
+                    .expect("template under the templates dir")
+                    .as_str()
+                    .replace('\\', "/");
+                let source = std::fs::read_to_string(&entry)
+                    .with_context(|| format!("failed to read template {}", entry))?;
+                env.add_template_owned(name, source)
+                    .with_context(|| format!("invalid template {}", entry))?;
             }
         }
+
+        Ok(Templates { env })
+    }
+
+    /// Renders `page.html` with the given context.
+    pub fn render_page(&self, context: &PageContext) -> Result<String> {
+        let template = self.env.get_template("page.html")?;
+        Ok(template.render(context)?)
+    }
+
+    /// Renders the page served for missing URLs, with the same layout and
+    /// navigation as the others.
+    pub fn render_not_found(
+        &self,
+        content: &Content,
+        tree: &[Node],
+        site: &SiteConfig,
+    ) -> Result<String> {
+        let page = Page::from(NOT_FOUND_PATH, NOT_FOUND_MARKDOWN.to_string())?;
+        let mut context = PageContext::new(content, tree, &page, site);
+        context.page.canonical_url = None;
+        self.render_page(&context)
     }
 }
 
-pub fn layout(sidebar: Markup, meta: Markup, content: Markup) -> Markup {
-    html! {
-        .layout {
-            .nav {
-                a .logo href="/" {
-                    "Matt's Wiki_"
-                }
-                .options {
-                    .search {
-                        button
-                            type="button"
-                            id="search-open"
-                            aria-haspopup="dialog"
-                            aria-controls="search-dialog"
-                            .search-button {
-                            "Search"
-                        }
-                    }
-                    button .burger aria-controls="sidebar" aria-expanded="sidebar" {
-                        (PreEscaped(BURGER_ICON))
-                    }
-                }
-            }
-            #sidebar { (sidebar) }
-            .meta { (meta) }
-            main { (content) }
-            (footer())
+/// Everything templates get to render a page.
+#[derive(Debug, Serialize)]
+pub struct PageContext<'a> {
+    /// The `[site]` section of the config.
+    pub site: &'a SiteConfig,
+
+    pub page: PageView<'a>,
+
+    /// Ancestors of the page, from the root down.
+    pub breadcrumbs: Vec<LinkView>,
+
+    /// Navigation tree of all pages.
+    pub tree: Vec<TreeEntry>,
+
+    /// Pages that link to this one, sorted by title.
+    pub backlinks: Vec<LinkView>,
+
+    /// Headings shown in the table of contents, levels 2 to 4.
+    pub toc: Vec<HeadingView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageView<'a> {
+    pub title: &'a str,
+    pub path: &'a str,
+    pub url: String,
+    pub canonical_url: Option<String>,
+    pub tags: &'a [String],
+    /// Rendered HTML of the page body.
+    pub content: &'a str,
+    pub headings: Vec<HeadingView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkView {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeadingView<'a> {
+    pub level: u8,
+    pub text: &'a str,
+    pub id: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeEntry {
+    pub name: String,
+    pub url: String,
+    /// Whether the entry is an ancestor of the rendered page.
+    pub expanded: bool,
+    pub children: Vec<TreeEntry>,
+}
+
+impl<'a> PageContext<'a> {
+    pub fn new(content: &'a Content, tree: &[Node], page: &'a Page, site: &'a SiteConfig) -> Self {
+        let breadcrumbs = breadcrumbs(content, page, &site.root_label);
+        let hierarchy: Vec<String> = breadcrumbs
+            .iter()
+            .map(|crumb| crumb.title.clone())
+            .collect();
+        let url = page_url(&page.path);
+        let headings: Vec<HeadingView> = page.headings.iter().map(HeadingView::from).collect();
+
+        PageContext {
+            site,
+            breadcrumbs,
+            tree: tree_entries(tree, slice_tail(&hierarchy)),
+            backlinks: content
+                .backlinks(&page.path)
+                .into_iter()
+                .map(|page| LinkView {
+                    title: page.title.clone(),
+                    url: page_url(&page.path),
+                })
+                .collect(),
+            toc: page
+                .headings
+                .iter()
+                .filter(|heading| (2..=4).contains(&heading.level))
+                .map(HeadingView::from)
+                .collect(),
+            page: PageView {
+                title: &page.title,
+                path: &page.path,
+                canonical_url: site.canonical_url(&url),
+                url,
+                tags: &page.tags,
+                content: &page.html,
+                headings,
+            },
         }
-        dialog #search-dialog {
-            .search-modal {
-                .search-modal__header {
-                    h2 { "Search" }
-                    button type="button" id="search-close" .search-close {
-                        "Close"
-                    }
-                }
-                div id="search" {}
-            }
+    }
+}
+
+impl<'a> From<&'a Heading> for HeadingView<'a> {
+    fn from(heading: &'a Heading) -> Self {
+        HeadingView {
+            level: heading.level,
+            text: &heading.text,
+            id: &heading.id,
+        }
+    }
+}
+
+/// Ancestors of a page from the root down, ending with the page itself.
+pub fn breadcrumbs(content: &Content, page: &Page, root_label: &str) -> Vec<LinkView> {
+    let mut trail = Vec::with_capacity(page.parents.len());
+    for parent in &page.parents {
+        if parent == "/" {
+            trail.push(LinkView {
+                title: root_label.into(),
+                url: parent.clone(),
+            });
+            continue;
         }
-        script {
-            (PreEscaped(
-                r#"
-                window.addEventListener('DOMContentLoaded', function() {
-                    new PagefindUI({ element: '#search', showSubResults: true });
-                });
-                "#
-            ))
+
+        if let Some(parent_page) = content.get(parent) {
+            trail.push(LinkView {
+                title: parent_page.title.clone(),
+                url: parent.clone(),
+            });
         }
     }
+    trail
 }
 
 fn slice_tail(hierarchy: &[String]) -> &[String] {
@@ -97,38 +261,53 @@ fn slice_tail(hierarchy: &[String]) -> &[String] {
     }
 }
 
-fn tree_node(node: &Node, hierarchy: &[String]) -> Markup {
-    let expanded = hierarchy.iter().any(|name| name == &node.name);
-    let tail = slice_tail(hierarchy);
-
-    html! {
-        .entry {
-            a .active[expanded] href=(node.path) {
-                (node.name)
-            }
-            @if !node.children.is_empty() {
-                button aria-controls=(node.name) aria-expanded=(expanded.to_string()) {
-                    span .icon {
-                        (PreEscaped(EXPAND_ICON))
-                    }
-                }
-                .folder .expanded[expanded] id=(node.name) {
-                    @for child in &node.children {
-                        (tree_node(child, tail))
-                    }
-                }
-            }
-        }
-    }
+fn tree_entries(nodes: &[Node], hierarchy: &[String]) -> Vec<TreeEntry> {
+    nodes
+        .iter()
+        .map(|node| TreeEntry {
+            name: node.name.clone(),
+            url: node.path.clone(),
+            expanded: hierarchy.iter().any(|name| name == &node.name),
+            children: tree_entries(&node.children, slice_tail(hierarchy)),
+        })
+        .collect()
 }
 
-pub fn content_navigation(children: &[Node], hierarchy: &[String]) -> Markup {
-    let top = slice_tail(hierarchy);
-    html! {
-        .tree {
-            @for child in children {
-                (tree_node(child, top))
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn render_with_overrides() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        std::fs::create_dir_all(root.join("templates/partials")).unwrap();
+        std::fs::create_dir_all(root.join("tools")).unwrap();
+        std::fs::write(root.join("tools.md"), "# Tools\n").unwrap();
+        std::fs::write(root.join("tools/git.md"), "# Git\n\n## Setup\n").unwrap();
+        std::fs::write(
+            root.join("templates/partials/footer.html"),
+            "<footer>{{ site.title }} · {{ page.path }}</footer>\n",
+        )
+        .unwrap();
+
+        let content = Content::from_dir(root.as_str()).await.unwrap();
+        let templates = Templates::load(root).unwrap();
+
+        let site = SiteConfig::default();
+        let page = content.get("/tools/git").unwrap();
+        let context = PageContext::new(&content, &content.build_tree(), page, &site);
+        let rendered = templates.render_page(&context).unwrap();
+        assert!(rendered.contains("<title>Git | Wiki</title>"));
+        assert!(rendered.contains("<footer>Wiki · /tools/git</footer>"));
+        assert!(rendered.contains(r#"<a class="active" href="/tools">Tools</a>"#));
+        assert!(rendered.contains(r#"<button aria-controls="Tools" aria-expanded="true">"#));
+        assert!(rendered.contains(r##"<li class="level-2"><a href="#setup">Setup</a></li>"##));
+
+        let not_found = templates
+            .render_not_found(&content, &content.build_tree(), &site)
+            .unwrap();
+        assert!(not_found.contains("<title>Page not found | Wiki</title>"));
+        assert!(not_found.contains(r#"<a href="/tools">Tools</a>"#));
     }
 }
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, fmt::Write, process::Command};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use html_escape::encode_text;
+use mwp_content::{Content, Page, page_url};
+use tracing::debug;
+
+use crate::config::{RobotsConfig, SiteConfig};
+
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+pub const ROBOTS_FILE: &str = "robots.txt";
+
+/// Sitemap of all pages, `None` without a base URL since sitemaps need
+/// absolute ones.
+///
+/// Pages are dated by the `date` in their front matter, falling back to the
+/// date in `dates`, keyed by page path.
+pub fn sitemap(
+    content: &Content,
+    site: &SiteConfig,
+    dates: &HashMap<String, String>,
+) -> Option<String> {
+    site.base_url.as_ref()?;
+
+    let mut pages: Vec<&Page> = content.all().values().collect();
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for page in pages {
+        let loc = site.canonical_url(&page_url(&page.path))?;
+        let lastmod = page
+            .meta
+            .date
+            .map(|date| date.to_string())
+            .or_else(|| dates.get(&page.path).cloned());
+        let _ = write!(xml, "  <url><loc>{}</loc>", encode_text(&loc));
+        if let Some(lastmod) = lastmod {
+            let _ = write!(xml, "<lastmod>{}</lastmod>", encode_text(&lastmod));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    Some(xml)
+}
+
+/// `robots.txt` asking crawlers to skip the disallowed paths, pointing them
+/// to the sitemap when there is one.
+pub fn robots(config: &RobotsConfig, site: &SiteConfig) -> String {
+    let mut robots = String::from("User-agent: *\n");
+    if config.disallow.is_empty() {
+        robots.push_str("Allow: /\n");
+    }
+    for path in &config.disallow {
+        let _ = writeln!(robots, "Disallow: {path}");
+    }
+    if let Some(sitemap) = site.canonical_url(SITEMAP_FILE) {
+        let _ = writeln!(robots, "\nSitemap: {sitemap}");
+    }
+    robots
+}
+
+/// Date of the last commit that changed each file under `root`, by path
+/// relative to it. Empty when `root` isn't in a git repository.
+pub fn git_dates(root: &Utf8Path) -> HashMap<Utf8PathBuf, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["-c", "core.quotePath=false", "log"])
+        .args(["--format=%x00%cs", "--name-only", "--relative"])
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!(stderr = %String::from_utf8_lossy(&output.stderr), "no git history for page dates");
+            return HashMap::new();
+        }
+        Err(err) => {
+            debug!(error = %err, "no git history for page dates");
+            return HashMap::new();
+        }
+    };
+
+    // Commits come newest first, each a NUL prefixed date followed by the
+    // files it changed.
+    let log = String::from_utf8_lossy(&output.stdout);
+    let mut dates = HashMap::new();
+    let mut date = "";
+    for line in log.lines() {
+        if let Some(commit_date) = line.strip_prefix('\0') {
+            date = commit_date;
+        } else if !line.is_empty() {
+            dates
+                .entry(Utf8PathBuf::from(line))
+                .or_insert_with(|| date.to_string());
+        }
+    }
+    dates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sitemap_and_robots() {
+        let content = Content::from_pages([
+            Page::from("/index", "# Home\n".to_string()).unwrap(),
+            Page::from(
+                "/tools/git",
+                "---\ndate: 2024-03-01\n---\n# Git & co\n".to_string(),
+            )
+            .unwrap(),
+            Page::from("/notes/My Notes", "# My notes\n".to_string()).unwrap(),
+        ]);
+        let mut site = SiteConfig::default();
+        let dates = HashMap::from([
+            ("/".to_string(), "2025-01-02".to_string()),
+            ("/tools/git".to_string(), "2025-01-02".to_string()),
+        ]);
+        let robots_config = RobotsConfig {
+            disallow: vec!["/private/".into()],
+        };
+
+        assert_eq!(sitemap(&content, &site, &dates), None);
+        assert_eq!(
+            robots(&robots_config, &site),
+            "User-agent: *\nDisallow: /private/\n"
+        );
+
+        site.base_url = Some("https://wiki.example.com/docs/".parse().unwrap());
+        assert_eq!(
+            sitemap(&content, &site, &dates).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+             <url><loc>https://wiki.example.com/docs/</loc><lastmod>2025-01-02</lastmod></url>\n  \
+             <url><loc>https://wiki.example.com/docs/notes/My%20Notes/</loc></url>\n  \
+             <url><loc>https://wiki.example.com/docs/tools/git/</loc><lastmod>2024-03-01</lastmod></url>\n\
+             </urlset>\n"
+        );
+        assert_eq!(
+            robots(&RobotsConfig::default(), &site),
+            "User-agent: *\nAllow: /\n\nSitemap: https://wiki.example.com/docs/sitemap.xml\n"
+        );
+    }
+}
@@ -0,0 +1,533 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_web::{
+    HttpResponse,
+    web::{self, Bytes, ServiceConfig},
+};
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use futures::stream;
+use mwp_content::{Content, Node, Page, load_page};
+use notify_debouncer_mini::{DebounceEventResult, new_debouncer, notify::RecursiveMode};
+use tokio::{runtime::Handle, sync::broadcast};
+use tracing::{info, warn};
+
+use crate::{
+    FetchArgs, FetchSettings, PageWriter, assets,
+    config::{self, Config},
+    copy_files, generate_pagefind_bundle, page_output_path, remove_output, render, write_assets,
+};
+
+/// Path browsers listen on for reload events.
+const RELOAD_PATH: &str = "/__mwp/reload";
+
+/// Script injected into pages in watch mode, reloading them after a rebuild.
+const RELOAD_SCRIPT: &str = r#"<script>new EventSource("/__mwp/reload").addEventListener("reload", () => location.reload());</script>"#;
+
+/// Time file events are collected over before a rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Time without changes after which the search index is regenerated, it
+/// fetches remote pages and is too slow to redo on every save.
+const PAGEFIND_DELAY: Duration = Duration::from_secs(10);
+
+/// Notifies open pages that the site was rebuilt.
+pub type Reload = broadcast::Sender<()>;
+
+/// Builds the wiki into `output_dir` and starts rebuilding it on every
+/// change under `wiki_root` in the background.
+pub fn start(wiki_root: &Utf8Path, output_dir: &Utf8Path, fetch: &FetchArgs) -> Result<Reload> {
+    let mut site = WatchedSite::load(wiki_root, output_dir, fetch.clone())?;
+    site.build_all()?;
+
+    let (reload, _) = broadcast::channel(16);
+    let (sender, events) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, sender)?;
+    debouncer
+        .watcher()
+        .watch(wiki_root.as_std_path(), RecursiveMode::Recursive)?;
+
+    let runtime = Handle::current();
+    thread::spawn({
+        let reload = reload.clone();
+        move || {
+            let _debouncer = debouncer;
+            site.run(events, &reload, &runtime);
+        }
+    });
+
+    info!(root = %wiki_root, "watching for changes");
+    Ok(reload)
+}
+
+/// Registers the reload event stream.
+pub fn configure(config: &mut ServiceConfig, reload: Reload) {
+    config
+        .app_data(web::Data::new(reload))
+        .route(RELOAD_PATH, web::get().to(reload_events));
+}
+
+/// Adds the live reload script to the end of a rendered page.
+pub fn inject_live_reload(html: &mut String) {
+    match html.rfind("</body>") {
+        Some(end) => html.insert_str(end, RELOAD_SCRIPT),
+        None => html.push_str(RELOAD_SCRIPT),
+    }
+}
+
+async fn reload_events(reload: web::Data<Reload>) -> HttpResponse {
+    let events = stream::unfold(reload.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(()) => {
+                    let event = Bytes::from_static(b"event: reload\ndata: \n\n");
+                    return Some((Ok::<_, Infallible>(event), receiver));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+/// A wiki built into the output directory, kept in memory between rebuilds.
+struct WatchedSite {
+    wiki_root: Utf8PathBuf,
+    output_dir: Utf8PathBuf,
+    fetch_args: FetchArgs,
+    config: Config,
+    fetch: FetchSettings,
+    /// Parsed pages by source file, before they're linked together.
+    sources: HashMap<PathBuf, Page>,
+    /// Modification times of the wiki's files, to tell changes apart from
+    /// the events reading the files during a build triggers.
+    modified: HashMap<PathBuf, SystemTime>,
+    content: Content,
+    tree: Vec<Node>,
+}
+
+impl WatchedSite {
+    fn load(wiki_root: &Utf8Path, output_dir: &Utf8Path, fetch_args: FetchArgs) -> Result<Self> {
+        let (config, fetch) = load_config(wiki_root, &fetch_args)?;
+        Ok(WatchedSite {
+            wiki_root: wiki_root.to_owned(),
+            output_dir: output_dir.to_owned(),
+            fetch_args,
+            config,
+            fetch,
+            sources: HashMap::new(),
+            modified: HashMap::new(),
+            content: Content::from_pages([]),
+            tree: Vec::new(),
+        })
+    }
+
+    fn run(
+        mut self,
+        events: mpsc::Receiver<DebounceEventResult>,
+        reload: &Reload,
+        runtime: &Handle,
+    ) {
+        // The search index is generated right after the initial build, then
+        // again once changes settle down.
+        let mut pagefind_due = Some(Instant::now());
+        loop {
+            let event = match pagefind_due {
+                Some(due) => events.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => events.recv().map_err(RecvTimeoutError::from),
+            };
+            match event {
+                Ok(Ok(events)) => {
+                    match self.rebuild(events.into_iter().map(|event| event.path).collect()) {
+                        Ok(true) => {
+                            let _ = reload.send(());
+                            pagefind_due = Some(Instant::now() + PAGEFIND_DELAY);
+                        }
+                        Ok(false) => {}
+                        Err(err) => warn!(error = %format!("{err:#}"), "rebuild failed"),
+                    }
+                }
+                Ok(Err(err)) => warn!(error = %err, "failed to watch for changes"),
+                Err(RecvTimeoutError::Timeout) => {
+                    pagefind_due = None;
+                    let generated = runtime.block_on(generate_pagefind_bundle(
+                        &self.wiki_root,
+                        &self.content,
+                        &self.output_dir.join("pagefind"),
+                        &self.fetch,
+                        &self.config.site,
+                    ));
+                    if let Err(err) = generated {
+                        warn!(error = %format!("{err:#}"), "failed to generate search index");
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Parses every page and writes the whole site except the search index.
+    fn build_all(&mut self) -> Result<()> {
+        self.scan_modified();
+        self.sources = self
+            .markdown_files()
+            .into_iter()
+            .filter_map(|path| Some((path.clone(), self.parse(&path)?)))
+            .collect();
+        self.content = Content::from_pages(self.sources.values().cloned());
+        self.tree = self.content.build_tree();
+
+        let paths: Vec<String> = self.content.all().keys().cloned().collect();
+        self.write_pages(paths)?;
+        write_assets(&self.wiki_root, &self.output_dir, &self.config.highlight)?;
+        copy_files(&self.config.walker(&self.wiki_root), &self.output_dir)?;
+        Ok(())
+    }
+
+    /// Rebuilds what the changed files affect, returning whether anything in
+    /// the output changed.
+    ///
+    /// Only changed pages and the pages showing them are re-rendered, unless
+    /// the change adds, removes, renames or reorders pages, which changes the
+    /// navigation tree on every page. Changes to the config, templates or assets rebuild
+    /// everything.
+    fn rebuild(&mut self, events: Vec<PathBuf>) -> Result<bool> {
+        // Directories that were created, moved or removed come as a single
+        // event, the files in them are found by scanning the wiki again.
+        let mut changed = Vec::new();
+        let mut directories = Vec::new();
+        for path in events {
+            if !self.is_source(&path) {
+                continue;
+            }
+            if path.is_dir() || (!path.exists() && !self.modified.contains_key(&path)) {
+                directories.push(path);
+            } else if self.has_changed(&path) {
+                changed.push(path);
+            }
+        }
+        if changed.is_empty() && directories.is_empty() {
+            return Ok(false);
+        }
+        if !directories.is_empty() {
+            self.scan_modified();
+        }
+
+        let root = self.wiki_root.as_std_path();
+        let settings_changed = changed.iter().chain(&directories).any(|path| {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            relative == Path::new(config::CONFIG_FILE)
+                || relative.starts_with(render::TEMPLATES_DIR)
+                || relative.starts_with(assets::ASSETS_DIR)
+        });
+        if settings_changed {
+            info!("site settings changed, rebuilding everything");
+            (self.config, self.fetch) = load_config(&self.wiki_root, &self.fetch_args)?;
+            self.build_all()?;
+            return Ok(true);
+        }
+
+        let copied = copy_files(&self.config.walker(&self.wiki_root), &self.output_dir)?.copied;
+
+        let files = self.markdown_files();
+        let removed: Vec<PathBuf> = self
+            .sources
+            .keys()
+            .filter(|path| !files.contains(*path))
+            .cloned()
+            .collect();
+        let modified: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|path| !self.sources.contains_key(path) || changed.contains(path))
+            .collect();
+        if removed.is_empty() && modified.is_empty() {
+            return Ok(copied > 0);
+        }
+
+        let mut changed_pages = HashSet::new();
+        for path in removed {
+            if let Some(page) = self.sources.remove(&path) {
+                remove_output(
+                    &self.output_dir,
+                    &page_output_path(Utf8Path::new(""), &page.path),
+                );
+                changed_pages.insert(page.path);
+            }
+        }
+        for path in modified {
+            // A page that fails to parse keeps its last good version.
+            if let Some(page) = self.parse(&path) {
+                changed_pages.insert(page.path.clone());
+                self.sources.insert(path, page);
+            }
+        }
+
+        let previous = std::mem::replace(
+            &mut self.content,
+            Content::from_pages(self.sources.values().cloned()),
+        );
+        let affected: Vec<String> = if outline(&previous) == outline(&self.content) {
+            affected_pages(&previous, &self.content, &changed_pages)
+                .into_iter()
+                .collect()
+        } else {
+            self.tree = self.content.build_tree();
+            self.content.all().keys().cloned().collect()
+        };
+        info!(pages = affected.len(), "re-rendering changed pages");
+        self.write_pages(affected)?;
+        Ok(true)
+    }
+
+    /// Records the modification times of all files in the wiki.
+    fn scan_modified(&mut self) {
+        self.modified = self
+            .config
+            .walker(&self.wiki_root)
+            .files()
+            .filter_map(Result::ok)
+            .filter_map(|path| Some((path.clone(), modified_time(&path)?)))
+            .collect();
+    }
+
+    /// Whether a file was modified, created or removed since it was last seen.
+    fn has_changed(&mut self, path: &Path) -> bool {
+        let current = modified_time(path);
+        let previous = match current {
+            Some(time) => self.modified.insert(path.to_path_buf(), time),
+            None => self.modified.remove(path),
+        };
+        previous != current
+    }
+
+    fn write_pages(&self, paths: impl IntoIterator<Item = String>) -> Result<()> {
+        let mut writer = PageWriter::new(
+            &self.wiki_root,
+            &self.output_dir,
+            &self.config,
+            &self.fetch,
+            false,
+            true,
+        )?;
+        for path in paths {
+            if let Some(page) = self.content.get(&path) {
+                writer.write(&self.content, &self.tree, page)?;
+            }
+        }
+        writer.write_not_found(&self.content, &self.tree)?;
+        Ok(())
+    }
+
+    fn markdown_files(&self) -> HashSet<PathBuf> {
+        self.config
+            .walker(&self.wiki_root)
+            .markdown_files()
+            .filter_map(|path| {
+                path.inspect_err(|err| warn!(error = %err, "failed to list page"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    fn parse(&self, path: &Path) -> Option<Page> {
+        load_page(self.wiki_root.as_std_path(), path, &self.config.markdown)
+            .inspect_err(|err| warn!(error = %err, "skipping page"))
+            .ok()
+    }
+
+    /// Whether a changed file may be part of the wiki, rather than the
+    /// output, the cache or a hidden file such as editor swap files.
+    fn is_source(&self, path: &Path) -> bool {
+        if path.starts_with(&self.output_dir) || path.starts_with(&self.fetch.cache_dir) {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.wiki_root) else {
+            return false;
+        };
+        !relative.components().any(|component| {
+            matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.'))
+        })
+    }
+}
+
+fn load_config(wiki_root: &Utf8Path, fetch_args: &FetchArgs) -> Result<(Config, FetchSettings)> {
+    let mut config = Config::load(wiki_root)?;
+    let fetch = FetchSettings::new(fetch_args, &config.fetch, wiki_root)?;
+    config.markdown.cache_dir = Some(fetch.cache_dir.join("diagrams").into_std_path_buf());
+    Ok((config, fetch))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Paths, titles and weights of all pages, the part of the content the
+/// navigation tree and breadcrumbs are built from.
+fn outline(content: &Content) -> Vec<(&str, &str, Option<i64>)> {
+    let mut outline: Vec<(&str, &str, Option<i64>)> = content
+        .all()
+        .values()
+        .map(|page| (page.path.as_str(), page.title.as_str(), page.meta.weight))
+        .collect();
+    outline.sort_unstable();
+    outline
+}
+
+/// Pages whose output depends on the `changed` ones: the pages themselves,
+/// the pages whose links resolve differently since, such as links to a
+/// changed alias, the pages either of them link to or used to link to, whose
+/// backlinks change, and the pages that embed any of them, directly or
+/// through other embeds.
+fn affected_pages(
+    previous: &Content,
+    current: &Content,
+    changed: &HashSet<String>,
+) -> HashSet<String> {
+    let mut rendered = changed.clone();
+    rendered.extend(
+        current
+            .all()
+            .values()
+            .filter(|page| {
+                previous
+                    .get(&page.path)
+                    .is_some_and(|before| !before.linked_pages().eq(page.linked_pages()))
+            })
+            .map(|page| page.path.clone()),
+    );
+
+    let mut affected = rendered.clone();
+    for path in &rendered {
+        for content in [previous, current] {
+            if let Some(page) = content.get(path) {
+                affected.extend(page.linked_pages().map(str::to_string));
+            }
+        }
+    }
+
+    let mut embedding = rendered;
+    loop {
+        let next: Vec<String> = current
+            .all()
+            .values()
+            .filter(|page| !embedding.contains(&page.path))
+            .filter(|page| {
+                // Embeds that stopped resolving still show the old target.
+                [Some(*page), previous.get(&page.path)]
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|page| &page.embeds)
+                    .any(|embed| {
+                        embed
+                            .resolved
+                            .as_ref()
+                            .is_some_and(|target| embedding.contains(target))
+                    })
+            })
+            .map(|page| page.path.clone())
+            .collect();
+        if next.is_empty() {
+            break;
+        }
+        embedding.extend(next);
+    }
+
+    affected.extend(embedding);
+    affected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(pages: &[(&str, &str)]) -> Content {
+        Content::from_pages(
+            pages
+                .iter()
+                .map(|(path, markdown)| Page::from(path, markdown.to_string()).unwrap()),
+        )
+    }
+
+    #[test]
+    fn affected_by_links_and_embeds() {
+        let previous = content(&[
+            ("/git", "# Git\n\nSee [[Tools]].\n"),
+            ("/tools", "# Tools\n"),
+            ("/jj", "# JJ\n"),
+            ("/summary", "# Summary\n\n![[Git]]\n"),
+            ("/overview", "# Overview\n\n![[Summary]]\n"),
+            ("/other", "# Other\n\n[[Git]]\n"),
+        ]);
+        let current = content(&[
+            ("/git", "# Git\n\nSee [[JJ]].\n"),
+            ("/tools", "# Tools\n"),
+            ("/jj", "# JJ\n"),
+            ("/summary", "# Summary\n\n![[Git]]\n"),
+            ("/overview", "# Overview\n\n![[Summary]]\n"),
+            ("/other", "# Other\n\n[[Git]]\n"),
+        ]);
+
+        let mut affected: Vec<String> =
+            affected_pages(&previous, &current, &HashSet::from(["/git".to_string()]))
+                .into_iter()
+                .collect();
+        affected.sort();
+        assert_eq!(affected, ["/git", "/jj", "/overview", "/summary", "/tools"]);
+        assert_eq!(outline(&previous), outline(&current));
+        let reordered = content(&[("/git", "---\nweight: 1\n---\n# Git\n")]);
+        assert_ne!(
+            outline(&content(&[("/git", "# Git\n")])),
+            outline(&reordered)
+        );
+
+        // Adding an alias resolves links and embeds on other pages, removing
+        // it breaks them again.
+        let previous = content(&[
+            ("/a", "# A\n\nSee [[Foo]].\n"),
+            ("/b", "# B\n"),
+            ("/c", "# C\n\n![[A]]\n"),
+            ("/d", "# D\n\n![[Foo]]\n"),
+        ]);
+        let current = content(&[
+            ("/a", "# A\n\nSee [[Foo]].\n"),
+            ("/b", "---\naliases: [Foo]\n---\n# B\n"),
+            ("/c", "# C\n\n![[A]]\n"),
+            ("/d", "# D\n\n![[Foo]]\n"),
+        ]);
+        let alias = HashSet::from(["/b".to_string()]);
+        let mut affected: Vec<String> = affected_pages(&previous, &current, &alias)
+            .into_iter()
+            .collect();
+        affected.sort();
+        assert_eq!(affected, ["/a", "/b", "/c", "/d"]);
+        let mut affected: Vec<String> = affected_pages(&current, &previous, &alias)
+            .into_iter()
+            .collect();
+        affected.sort();
+        assert_eq!(affected, ["/a", "/b", "/c", "/d"]);
+    }
+
+    #[test]
+    fn inject_reload_script() {
+        let mut html = String::from("<html><body><p>Hi</p></body></html>");
+        inject_live_reload(&mut html);
+        assert_eq!(
+            html,
+            format!("<html><body><p>Hi</p>{RELOAD_SCRIPT}</body></html>")
+        );
+    }
+}
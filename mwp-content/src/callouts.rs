@@ -0,0 +1,193 @@
+use pulldown_cmark::{Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+
+/// First line of a callout block quote, e.g. `> [!tip]- Title`.
+struct Header<'a> {
+    kind: String,
+    /// `None` for regular callouts, whether the callout starts open for
+    /// collapsible ones.
+    open: Option<bool>,
+    title: Vec<Event<'a>>,
+    /// Whether the first paragraph continues after the header line.
+    continues: bool,
+    /// Number of events the header spans.
+    consumed: usize,
+}
+
+/// Turns block quotes starting with `[!type]` into callouts.
+///
+/// `> [!note] Title` becomes an `aside.callout.callout-note`, while a `-` or
+/// `+` after the type makes a `details.callout` that starts collapsed or
+/// expanded. Without a title the capitalized type is used.
+pub(crate) fn render_callouts<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut closers: Vec<Option<&'static str>> = Vec::new();
+
+    let mut i = 0;
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::BlockQuote(_)) => match parse_header(&events[i + 1..]) {
+                Some(header) => {
+                    let mut class = String::new();
+                    escape_html(&mut class, &header.kind).expect("write to string");
+                    let (open, title_tag) = match header.open {
+                        None => (format!("<aside class=\"callout callout-{class}\">\n"), "p"),
+                        Some(true) => (
+                            format!("<details class=\"callout callout-{class}\" open>\n"),
+                            "summary",
+                        ),
+                        Some(false) => (
+                            format!("<details class=\"callout callout-{class}\">\n"),
+                            "summary",
+                        ),
+                    };
+                    output.push(Event::Html(
+                        format!("{open}<{title_tag} class=\"callout-title\">").into(),
+                    ));
+                    if header.title.is_empty() {
+                        output.push(Event::Text(default_title(&header.kind).into()));
+                    } else {
+                        output.extend(header.title);
+                    }
+                    output.push(Event::Html(
+                        format!("</{title_tag}>\n<div class=\"callout-body\">\n").into(),
+                    ));
+                    if header.continues {
+                        output.push(Event::Start(Tag::Paragraph));
+                    }
+
+                    closers.push(Some(match header.open {
+                        None => "</div>\n</aside>\n",
+                        Some(_) => "</div>\n</details>\n",
+                    }));
+                    i += 1 + header.consumed;
+                    continue;
+                }
+                None => {
+                    closers.push(None);
+                    output.push(events[i].clone());
+                }
+            },
+            Event::End(TagEnd::BlockQuote(_)) => match closers.pop().flatten() {
+                Some(closer) => output.push(Event::Html(closer.into())),
+                None => output.push(events[i].clone()),
+            },
+            event => output.push(event.clone()),
+        }
+        i += 1;
+    }
+
+    output
+}
+
+fn parse_header<'a>(events: &[Event<'a>]) -> Option<Header<'a>> {
+    if !matches!(events.first(), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+
+    // The marker is usually split into several text events around the brackets.
+    let mut position = 1;
+    let mut text = String::new();
+    while let Some(Event::Text(chunk)) = events.get(position) {
+        text.push_str(chunk);
+        position += 1;
+    }
+
+    let (kind, rest) = text.strip_prefix("[!")?.split_once(']')?;
+    if kind.is_empty()
+        || !kind
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    let (open, rest) = match rest.chars().next() {
+        Some('-') => (Some(false), &rest[1..]),
+        Some('+') => (Some(true), &rest[1..]),
+        _ => (None, rest),
+    };
+
+    let mut title = Vec::new();
+    let rest = rest.trim_start();
+    if !rest.is_empty() {
+        title.push(Event::Text(rest.to_string().into()));
+    }
+    loop {
+        match events.get(position)? {
+            Event::SoftBreak | Event::HardBreak => {
+                return Some(Header {
+                    kind: kind.to_ascii_lowercase(),
+                    open,
+                    title,
+                    continues: true,
+                    consumed: position + 1,
+                });
+            }
+            Event::End(TagEnd::Paragraph) => {
+                return Some(Header {
+                    kind: kind.to_ascii_lowercase(),
+                    open,
+                    title,
+                    continues: false,
+                    consumed: position + 1,
+                });
+            }
+            event => title.push(event.clone()),
+        }
+        position += 1;
+    }
+}
+
+fn default_title(kind: &str) -> String {
+    let mut chars = kind.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{Parser, html};
+
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            render_callouts(Parser::new(markdown).collect()).into_iter(),
+        );
+        output
+    }
+
+    #[test]
+    fn callout_with_title() {
+        assert_eq!(
+            render("> [!WARNING] Mind *the* gap\n> Body text\n>\n> > nested quote\n"),
+            "<aside class=\"callout callout-warning\">\n\
+             <p class=\"callout-title\">Mind <em>the</em> gap</p>\n\
+             <div class=\"callout-body\">\n\
+             <p>Body text</p>\n\
+             <blockquote>\n<p>nested quote</p>\n</blockquote>\n\
+             </div>\n</aside>\n"
+        );
+    }
+
+    #[test]
+    fn collapsible_callouts() {
+        assert_eq!(
+            render("> [!tip]-\n>\n> Hidden\n\n> [!faq]+ Open\n\n> [not a callout]\n"),
+            "<details class=\"callout callout-tip\">\n\
+             <summary class=\"callout-title\">Tip</summary>\n\
+             <div class=\"callout-body\">\n\
+             <p>Hidden</p>\n\
+             </div>\n</details>\n\
+             <details class=\"callout callout-faq\" open>\n\
+             <summary class=\"callout-title\">Open</summary>\n\
+             <div class=\"callout-body\">\n\
+             </div>\n</details>\n\
+             <blockquote>\n<p>[not a callout]</p>\n</blockquote>\n"
+        );
+    }
+}
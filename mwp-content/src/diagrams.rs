@@ -0,0 +1,225 @@
+use std::{
+    cell::Cell,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder},
+};
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use sha1::{Digest, Sha1};
+
+/// Bumped whenever the rendered output changes, invalidating cached diagrams.
+const CACHE_VERSION: &str = "1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Dot,
+    Mermaid,
+}
+
+impl Kind {
+    fn from_info(info: &str) -> Option<Self> {
+        match info.split_whitespace().next()? {
+            "dot" | "graphviz" => Some(Kind::Dot),
+            "mermaid" => Some(Kind::Mermaid),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Dot => "dot",
+            Kind::Mermaid => "mermaid",
+        }
+    }
+
+    fn render(self, source: &str) -> Result<String, String> {
+        // Both renderers assert on some input they can't lay out.
+        guarded(|| match self {
+            Kind::Dot => render_dot(source),
+            Kind::Mermaid => mermaid_rs_renderer::render(source).map_err(|err| err.to_string()),
+        })?
+    }
+}
+
+thread_local! {
+    /// Whether a panic on this thread is caught by [`guarded`].
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the current thread is rendering a diagram. Renderers panic on
+/// some input, those panics are caught and reported as page warnings, so a
+/// panic hook can use this to skip printing them.
+pub fn rendering_diagram() -> bool {
+    GUARDED.get()
+}
+
+/// Runs `f`, turning a panic into an error.
+fn guarded<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let outer = GUARDED.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.set(outer);
+    result.map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown error");
+        format!("renderer panicked: {message}")
+    })
+}
+
+/// Replaces ` ```dot ` and ` ```mermaid ` blocks with inline SVG.
+///
+/// Rendered diagrams are stored in `cache_dir` by a hash of their source.
+/// Blocks that fail to render are described in `warnings` and left for the
+/// regular code block renderer.
+pub(crate) fn render_diagrams<'a>(
+    events: Vec<Event<'a>>,
+    cache_dir: Option<&Path>,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut block: Option<(Kind, Vec<Event<'a>>)> = None;
+
+    for event in events {
+        match (event, block.as_mut()) {
+            (Event::End(TagEnd::CodeBlock), Some(_)) => {
+                let (kind, mut events) = block.take().expect("open diagram block");
+                let source: String = events
+                    .iter()
+                    .filter_map(|event| match event {
+                        Event::Text(text) => Some(text.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+                match render_cached(kind, &source, cache_dir) {
+                    Ok(svg) => output.push(Event::Html(
+                        format!(
+                            "<figure class=\"diagram diagram-{}\">{}</figure>\n",
+                            kind.as_str(),
+                            svg
+                        )
+                        .into(),
+                    )),
+                    Err(err) => {
+                        warnings.push(format!(
+                            "failed to render {} diagram: {}",
+                            kind.as_str(),
+                            err
+                        ));
+                        events.push(Event::End(TagEnd::CodeBlock));
+                        output.extend(events);
+                    }
+                }
+            }
+            (event, Some((_, events))) => events.push(event),
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), None) => {
+                let start = Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info.clone())));
+                match Kind::from_info(&info) {
+                    Some(kind) => block = Some((kind, vec![start])),
+                    None => output.push(start),
+                }
+            }
+            (event, None) => output.push(event),
+        }
+    }
+
+    output
+}
+
+fn render_cached(kind: Kind, source: &str, cache_dir: Option<&Path>) -> Result<String, String> {
+    let Some(cache_dir) = cache_dir else {
+        return kind.render(source);
+    };
+
+    let mut hasher = Sha1::new();
+    hasher.update(CACHE_VERSION);
+    hasher.update(kind.as_str());
+    hasher.update(source);
+    let path = cache_dir.join(format!("{:x}.svg", hasher.finalize()));
+
+    if let Ok(svg) = fs::read_to_string(&path) {
+        return Ok(svg);
+    }
+
+    let svg = kind.render(source)?;
+    // The cache only saves work, a diagram that can't be stored is still rendered.
+    let _ = fs::create_dir_all(cache_dir).and_then(|_| fs::write(&path, &svg));
+    Ok(svg)
+}
+
+fn render_dot(source: &str) -> Result<String, String> {
+    let graph = DotParser::new(source)
+        .process()
+        .map_err(|err| format!("invalid graph: {err}"))?;
+
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut writer = SVGWriter::new();
+    builder.get().do_it(false, false, false, &mut writer);
+    let svg = writer.finalize();
+
+    // Drop the XML prolog, the SVG is inlined into HTML.
+    Ok(match svg.find("<svg") {
+        Some(start) => svg[start..].to_string(),
+        None => svg,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{Parser, html};
+
+    use super::*;
+
+    fn render_markdown(markdown: &str, cache_dir: Option<&Path>) -> (String, Vec<String>) {
+        let events = Parser::new(markdown).collect();
+        let mut warnings = Vec::new();
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            render_diagrams(events, cache_dir, &mut warnings).into_iter(),
+        );
+        (output, warnings)
+    }
+
+    #[test]
+    fn diagrams_to_svg() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache_dir = tmp.path().join("diagrams");
+        let markdown = "```dot\ndigraph { a -> b; }\n```\n\n```mermaid\nflowchart LR; A-->B\n```\n";
+
+        let (html, warnings) = render_markdown(markdown, Some(&cache_dir));
+        assert!(warnings.is_empty());
+        assert!(html.starts_with("<figure class=\"diagram diagram-dot\"><svg"));
+        assert!(html.contains("<figure class=\"diagram diagram-mermaid\"><svg"));
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+        let (again, _) = render_markdown(markdown, Some(&cache_dir));
+        assert_eq!(html, again);
+    }
+
+    #[test]
+    fn broken_diagrams_stay_code() {
+        let (html, warnings) = render_markdown("```mermaid\nflowchart LR; A-->\n```\n", None);
+
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-mermaid\">flowchart LR; A--&gt;\n</code></pre>\n"
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("failed to render mermaid diagram: "));
+
+        assert_eq!(
+            guarded(|| -> () { panic!("no layout") }),
+            Err("renderer panicked: no layout".to_string())
+        );
+        assert_eq!(guarded(rendering_diagram), Ok(true));
+        assert!(!rendering_diagram());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use pulldown_cmark_escape::{FmtWriter, escape_href, escape_html};
+use serde::{Deserialize, Serialize};
+
+use crate::{Content, Page, UnresolvedLink, links, page_url, slugify};
+
+/// An `![[target]]` or `![[target#heading]]` embed found on a page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Embed {
+    /// Page to embed, given as a title, path or alias.
+    pub target: String,
+
+    /// Heading whose section is embedded, the whole page if `None`.
+    pub fragment: Option<String>,
+
+    /// Path of the page the target resolved to, filled in by [`Content`].
+    pub resolved: Option<String>,
+}
+
+impl Embed {
+    pub(crate) fn new(destination: &str) -> Self {
+        let (target, fragment) = match destination.split_once('#') {
+            Some((target, fragment)) => (target, Some(fragment.trim().to_string())),
+            None => (destination, None),
+        };
+
+        Embed {
+            target: target.trim().to_string(),
+            fragment,
+            resolved: None,
+        }
+    }
+}
+
+/// Whether an `![[...]]` destination refers to a page rather than a file,
+/// like an image, that is rendered as is.
+pub(crate) fn is_page_target(destination: &str) -> bool {
+    let target = destination.split('#').next().unwrap_or_default();
+    let name = target.trim().rsplit('/').next().unwrap_or_default();
+    !links::has_file_extension(name)
+}
+
+/// Element emitted in place of an embed until the embedded content is known.
+pub(crate) fn placeholder(index: usize) -> String {
+    format!("<mwp-embed data-embed=\"{index}\"></mwp-embed>")
+}
+
+/// Lifts embeds that make up a whole paragraph out of it, the embedded
+/// content is block level.
+pub(crate) fn unwrap_paragraphs(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        if matches!(event, Event::End(TagEnd::Paragraph))
+            && let [.., Event::Start(Tag::Paragraph), Event::InlineHtml(html)] = output.as_slice()
+            && html.starts_with("<mwp-embed ")
+        {
+            let html = html.clone();
+            output.truncate(output.len() - 2);
+            output.push(Event::Html(html));
+            continue;
+        }
+        output.push(event);
+    }
+    output
+}
+
+struct Expanded {
+    html: String,
+    text: String,
+    /// Whether the expansion ran into a cycle, which makes the result depend
+    /// on where the expansion started.
+    cyclic: bool,
+}
+
+impl Content {
+    /// Resolves embeds on all pages and replaces their placeholders with the
+    /// embedded content, following embeds recursively.
+    pub(crate) fn expand_embeds(&mut self) {
+        let mut resolved: Vec<(String, Vec<Option<String>>)> = Vec::new();
+        for page in self.pages.values() {
+            if page.embeds.is_empty() {
+                continue;
+            }
+            let targets = page
+                .embeds
+                .iter()
+                .map(|embed| {
+                    self.resolve(page, &embed.target)
+                        .map(|target| target.path.clone())
+                })
+                .collect();
+            resolved.push((page.path.clone(), targets));
+        }
+
+        for (path, targets) in &resolved {
+            let Some(page) = self.pages.get_mut(path) else {
+                continue;
+            };
+            for (embed, target) in page.embeds.iter_mut().zip(targets) {
+                if target.is_none() {
+                    self.unresolved.push(UnresolvedLink {
+                        page: path.clone(),
+                        target: embed.target.clone(),
+                    });
+                }
+                embed.resolved = target.clone();
+            }
+        }
+        self.unresolved
+            .sort_by(|a, b| (&a.page, &a.target).cmp(&(&b.page, &b.target)));
+
+        let mut expanded = HashMap::new();
+        let mut results = Vec::with_capacity(resolved.len());
+        for (path, _) in resolved {
+            let result = self.expand(&path, &mut Vec::new(), &mut expanded);
+            results.push((path, result));
+        }
+        for (path, result) in results {
+            if let Some(page) = self.pages.get_mut(&path) {
+                page.html = result.html;
+                page.text = result.text;
+            }
+        }
+    }
+
+    fn expand(
+        &self,
+        path: &str,
+        stack: &mut Vec<String>,
+        expanded: &mut HashMap<String, (String, String)>,
+    ) -> Expanded {
+        let page = &self.pages[path];
+        if let Some((html, text)) = expanded.get(path) {
+            return Expanded {
+                html: html.clone(),
+                text: text.clone(),
+                cyclic: false,
+            };
+        }
+        if page.embeds.is_empty() {
+            return Expanded {
+                html: page.html.clone(),
+                text: page.text.clone(),
+                cyclic: false,
+            };
+        }
+
+        stack.push(path.to_string());
+        let mut html = page.html.clone();
+        let mut text = page.text.clone();
+        let mut cyclic = false;
+        for (index, embed) in page.embeds.iter().enumerate() {
+            let replacement = match &embed.resolved {
+                None => missing("Missing page", &embed.target),
+                Some(target) if stack.contains(target) => {
+                    cyclic = true;
+                    missing("Embed cycle", &embed.target)
+                }
+                Some(target) => {
+                    let inner = self.expand(target, stack, expanded);
+                    cyclic |= inner.cyclic;
+                    let target = &self.pages[target];
+                    match section(target, &inner.html, embed.fragment.as_deref()) {
+                        Some(section) => {
+                            text.push('\n');
+                            text.push_str(&html_text(section));
+                            let notes = footnotes(&inner.html)
+                                .filter(|_| section.contains("class=\"footnote-ref\""));
+                            let section = scope_ids(section, notes, index + 1);
+                            wrap(target, embed.fragment.as_deref(), &section)
+                        }
+                        None => missing(
+                            "Missing section",
+                            &format!(
+                                "{}#{}",
+                                embed.target,
+                                embed.fragment.as_deref().unwrap_or_default()
+                            ),
+                        ),
+                    }
+                }
+            };
+            html = html.replacen(&placeholder(index), &replacement, 1);
+        }
+        stack.pop();
+
+        if !cyclic {
+            expanded.insert(path.to_string(), (html.clone(), text.clone()));
+        }
+        Expanded { html, text, cyclic }
+    }
+}
+
+const FOOTNOTES: &str = "<section class=\"footnotes\">";
+
+/// Part of the rendered page that belongs under the heading matching
+/// `fragment`, or all of it without footnotes if there is no fragment.
+fn section<'h>(page: &Page, html: &'h str, fragment: Option<&str>) -> Option<&'h str> {
+    let html = html.split(FOOTNOTES).next().unwrap_or(html);
+    let Some(fragment) = fragment.filter(|fragment| !fragment.is_empty()) else {
+        return Some(html);
+    };
+
+    let slug = slugify(fragment);
+    let position = page
+        .headings
+        .iter()
+        .position(|heading| heading.id == fragment || heading.id == slug)?;
+    let heading = &page.headings[position];
+    let start = html.find(&format!("<h{} id=\"{}\"", heading.level, heading.id))?;
+    let end = page.headings[position + 1..]
+        .iter()
+        .filter(|next| next.level <= heading.level)
+        .find_map(|next| html[start..].find(&format!("<h{} id=\"{}\"", next.level, next.id)))
+        .map_or(html.len(), |offset| start + offset);
+
+    Some(&html[start..end])
+}
+
+/// Footnotes at the end of the rendered page, if it has any.
+fn footnotes(html: &str) -> Option<&str> {
+    html.find(FOOTNOTES).map(|start| &html[start..])
+}
+
+/// Embedded section followed by the footnotes it refers to, with its ids and
+/// the links to them prefixed by `embed-{number}-`. Otherwise they'd clash
+/// with the headings and footnotes of the embedding page.
+fn scope_ids(section: &str, footnotes: Option<&str>, number: usize) -> String {
+    let mut html = section.to_string();
+    if let Some(footnotes) = footnotes {
+        // Marked apart so the notes stay with the embed once this page is
+        // embedded elsewhere.
+        html.push_str(&footnotes.replacen(FOOTNOTES, "<section class=\"footnotes embedded\">", 1));
+    }
+
+    let prefix = format!("embed-{number}-");
+    html.replace(" id=\"", &format!(" id=\"{prefix}"))
+        .replace(" href=\"#", &format!(" href=\"#{prefix}"))
+}
+
+fn wrap(target: &Page, fragment: Option<&str>, section: &str) -> String {
+    let mut href = page_url(&target.path);
+    if let Some(fragment) = fragment.filter(|fragment| !fragment.is_empty()) {
+        href.push('#');
+        href.push_str(&slugify(fragment));
+    }
+
+    let mut html = String::from("<div class=\"embed\">\n");
+    html.push_str(section);
+    html.push_str("<p class=\"embed-source\"><a href=\"");
+    escape_href(FmtWriter(&mut html), &href).expect("write href");
+    html.push_str("\">");
+    escape_html(FmtWriter(&mut html), &target.title).expect("write title");
+    html.push_str("</a></p>\n</div>\n");
+    html
+}
+
+fn missing(reason: &str, target: &str) -> String {
+    let mut html = format!("<div class=\"embed missing\">{reason}: ");
+    escape_html(FmtWriter(&mut html), target).expect("write target");
+    html.push_str("</div>\n");
+    html
+}
+
+/// Plain text of rendered HTML, for search.
+fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(pages: &[(&str, &str)]) -> Content {
+        Content::from_pages(
+            pages
+                .iter()
+                .map(|(path, markdown)| Page::from(path, markdown.to_string()).unwrap()),
+        )
+    }
+
+    #[test]
+    fn embed_pages_and_sections() {
+        let content = content(&[
+            (
+                "/glossary",
+                "# Glossary\n\n## DAG\n\nDirected acyclic graph.\n\n### Usage\n\nSee [[Git]].\n\n## CRDT\n\nReplicated type.\n",
+            ),
+            ("/git", "# Git\n\n![[glossary#DAG]]\n\n![[snippet]]\n"),
+            (
+                "/snippet",
+                "Shared snippet.\n\n![[git]]\n\n![[release-1.2]]\n\n![[v2.0 notes]]\n",
+            ),
+            ("/release-1.2", "Released.\n"),
+            ("/v2.0 notes", "Notes on v2.\n"),
+        ]);
+
+        let git = content.get("/git").unwrap();
+        assert!(git.html.contains(
+            "<div class=\"embed\">\n<h2 id=\"embed-1-dag\">DAG<a class=\"anchor\" href=\"#embed-1-dag\" aria-label=\"Link to this section\">#</a></h2>\n\
+             <p>Directed acyclic graph.</p>\n<h3 id=\"embed-1-usage\">"
+        ));
+        assert!(!git.html.contains("Replicated type."));
+        assert!(
+            git.html
+                .contains("<p class=\"embed-source\"><a href=\"/glossary/#dag\">Glossary</a></p>")
+        );
+        assert!(
+            git.html
+                .contains("<div class=\"embed missing\">Embed cycle: git</div>")
+        );
+        assert!(git.text.contains("Directed acyclic graph."));
+        assert!(git.text.contains("Shared snippet."));
+        assert!(git.text.contains("Released."));
+        assert!(git.text.contains("Notes on v2."));
+
+        // Embedded links count for search but not for the link graph.
+        assert_eq!(git.linked_pages().count(), 0);
+        assert!(
+            content
+                .backlinks("/git")
+                .iter()
+                .all(|page| page.path != "/git")
+        );
+    }
+
+    #[test]
+    fn embed_scopes_ids() {
+        let content = content(&[
+            (
+                "/a",
+                "# A\n\n## Setup\n\nOwn note.[^1]\n\n![[b]]\n\n[^1]: Note of A.\n",
+            ),
+            ("/b", "## Setup\n\nEmbedded note.[^1]\n\n[^1]: Note of B.\n"),
+        ]);
+
+        let a = content.get("/a").unwrap();
+        assert_eq!(a.html.matches(" id=\"setup\"").count(), 1);
+        assert!(a.html.contains("<h2 id=\"embed-1-setup\">"));
+        assert_eq!(a.html.matches(" id=\"fn-1\"").count(), 1);
+        assert!(a.html.contains("<a href=\"#embed-1-fn-1\">1</a>"));
+        assert!(a.html.contains("<li id=\"embed-1-fn-1\">\n<p>Note of B."));
+        assert!(a.html.ends_with(
+            "<section class=\"footnotes\">\n<ol>\n<li id=\"fn-1\">\n<p>Note of A. \
+             <a href=\"#fnref-1\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">↩</a></p>\n\
+             </li>\n</ol>\n</section>\n"
+        ));
+    }
+
+    #[test]
+    fn embed_missing_targets() {
+        let content = content(&[
+            ("/a", "![[nowhere]]\n\n![[b#nope]] ![[image.png]]\n"),
+            ("/b", "# B\n"),
+        ]);
+
+        let a = content.get("/a").unwrap();
+        assert!(
+            a.html
+                .starts_with("<div class=\"embed missing\">Missing page: nowhere</div>\n")
+        );
+        assert!(
+            a.html
+                .contains("<div class=\"embed missing\">Missing section: b#nope</div>")
+        );
+        assert!(a.html.contains("<img src=\"/image.png\""));
+        assert_eq!(
+            content.unresolved_links(),
+            &[UnresolvedLink {
+                page: "/a".into(),
+                target: "nowhere".into(),
+            }]
+        );
+    }
+}
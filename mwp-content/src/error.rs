@@ -0,0 +1,69 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Errors that can occur while loading wiki content.
+#[derive(Debug)]
+pub enum Error {
+    /// The content directory couldn't be traversed.
+    Walk {
+        path: PathBuf,
+        source: ignore::Error,
+    },
+
+    /// A file couldn't be read.
+    Read { path: PathBuf, source: io::Error },
+
+    /// A file isn't valid UTF-8.
+    InvalidUtf8 { path: PathBuf },
+
+    /// A file path can't be turned into a page path.
+    InvalidPath { path: PathBuf, reason: &'static str },
+
+    /// The front matter of a page couldn't be parsed.
+    FrontMatter { path: PathBuf, message: String },
+}
+
+impl Error {
+    /// Path of the file the error relates to.
+    pub fn path(&self) -> &Path {
+        match self {
+            Error::Walk { path, .. }
+            | Error::Read { path, .. }
+            | Error::InvalidUtf8 { path }
+            | Error::InvalidPath { path, .. }
+            | Error::FrontMatter { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Walk { path, source } => {
+                write!(f, "failed to walk {}: {}", path.display(), source)
+            }
+            Error::Read { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            Error::InvalidUtf8 { path } => write!(f, "{} is not valid UTF-8", path.display()),
+            Error::InvalidPath { path, reason } => {
+                write!(f, "invalid path {}: {}", path.display(), reason)
+            }
+            Error::FrontMatter { path, message } => {
+                write!(f, "invalid front matter in {}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Walk { source, .. } => Some(source),
+            Error::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+/// Moves footnote definitions into a numbered list at the end of the page.
+///
+/// Notes are numbered in the order they're first referenced and each of them
+/// links back to every reference. Definitions nobody references are dropped,
+/// references without a definition are kept as plain text.
+pub(crate) fn collect_footnotes<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut body = Vec::with_capacity(events.len());
+    let mut definitions: HashMap<CowStr<'a>, Vec<Event<'a>>> = HashMap::new();
+    let mut current: Option<(CowStr<'a>, Vec<Event<'a>>)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => current = Some((label, Vec::new())),
+            Event::End(TagEnd::FootnoteDefinition) => {
+                if let Some((label, events)) = current.take() {
+                    definitions.entry(label).or_insert(events);
+                }
+            }
+            event => match current.as_mut() {
+                Some((_, events)) => events.push(event),
+                None => body.push(event),
+            },
+        }
+    }
+
+    let mut numbering = Numbering::default();
+    let mut output = numbering.link(body, &definitions);
+
+    // Notes can reference other notes, which may append to the order while
+    // it's being walked.
+    let mut notes = Vec::new();
+    while let Some(label) = numbering.order.get(notes.len()).cloned() {
+        let events = definitions.remove(&label).unwrap_or_default();
+        notes.push(numbering.link(events, &definitions));
+    }
+    if notes.is_empty() {
+        return output;
+    }
+
+    output.push(Event::Html("<section class=\"footnotes\">\n<ol>\n".into()));
+    for (index, mut events) in notes.into_iter().enumerate() {
+        let number = index + 1;
+        let label = &numbering.order[index];
+        let backrefs = Event::InlineHtml(backrefs(number, numbering.references[label].1).into());
+
+        output.push(Event::Html(format!("<li id=\"fn-{number}\">\n").into()));
+        match events.pop() {
+            Some(Event::End(TagEnd::Paragraph)) => {
+                events.push(backrefs);
+                events.push(Event::End(TagEnd::Paragraph));
+            }
+            Some(event) => {
+                events.push(event);
+                events.push(backrefs);
+            }
+            None => events.push(backrefs),
+        }
+        output.extend(events);
+        output.push(Event::Html("</li>\n".into()));
+    }
+    output.push(Event::Html("</ol>\n</section>\n".into()));
+
+    output
+}
+
+#[derive(Debug, Default)]
+struct Numbering<'a> {
+    /// Labels in the order they were first referenced.
+    order: Vec<CowStr<'a>>,
+
+    /// Number and reference count of each label.
+    references: HashMap<CowStr<'a>, (usize, usize)>,
+}
+
+impl<'a> Numbering<'a> {
+    fn link(
+        &mut self,
+        events: Vec<Event<'a>>,
+        definitions: &HashMap<CowStr<'a>, Vec<Event<'a>>>,
+    ) -> Vec<Event<'a>> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                Event::FootnoteReference(label)
+                    if definitions.contains_key(&label) || self.references.contains_key(&label) =>
+                {
+                    if !self.references.contains_key(&label) {
+                        self.order.push(label.clone());
+                        self.references.insert(label.clone(), (self.order.len(), 0));
+                    }
+                    let (number, count) = self.references.get_mut(&label).expect("numbered note");
+                    *count += 1;
+                    Event::InlineHtml(
+                        format!(
+                            "<sup class=\"footnote-ref\" id=\"{}\"><a href=\"#fn-{number}\">{number}</a></sup>",
+                            reference_id(*number, *count)
+                        )
+                        .into(),
+                    )
+                }
+                Event::FootnoteReference(label) => Event::Text(format!("[^{label}]").into()),
+                event => event,
+            })
+            .collect()
+    }
+}
+
+fn reference_id(number: usize, occurrence: usize) -> String {
+    if occurrence == 1 {
+        format!("fnref-{number}")
+    } else {
+        format!("fnref-{number}-{occurrence}")
+    }
+}
+
+fn backrefs(number: usize, count: usize) -> String {
+    (1..=count)
+        .map(|occurrence| {
+            let marker = if occurrence == 1 {
+                String::new()
+            } else {
+                format!("<sup>{occurrence}</sup>")
+            };
+            format!(
+                " <a href=\"#{}\" class=\"footnote-backref\" aria-label=\"Back to reference {number}\">↩{marker}</a>",
+                reference_id(number, occurrence)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{Options, Parser, html};
+
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        let events = Parser::new_ext(markdown, Options::ENABLE_FOOTNOTES).collect();
+        let mut output = String::new();
+        html::push_html(&mut output, collect_footnotes(events).into_iter());
+        output
+    }
+
+    #[test]
+    fn footnotes_are_numbered_by_first_reference() {
+        let html = render(
+            "[^b] first, [^a] second, [^b] again.\n\n[^a]: Note A.\n[^b]: Note B.\n[^unused]: Nope.\n",
+        );
+
+        assert_eq!(
+            html,
+            "<p><sup class=\"footnote-ref\" id=\"fnref-1\"><a href=\"#fn-1\">1</a></sup> first, \
+             <sup class=\"footnote-ref\" id=\"fnref-2\"><a href=\"#fn-2\">2</a></sup> second, \
+             <sup class=\"footnote-ref\" id=\"fnref-1-2\"><a href=\"#fn-1\">1</a></sup> again.</p>\n\
+             <section class=\"footnotes\">\n<ol>\n\
+             <li id=\"fn-1\">\n<p>Note B. \
+             <a href=\"#fnref-1\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">↩</a> \
+             <a href=\"#fnref-1-2\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">↩<sup>2</sup></a></p>\n\
+             </li>\n\
+             <li id=\"fn-2\">\n<p>Note A. \
+             <a href=\"#fnref-2\" class=\"footnote-backref\" aria-label=\"Back to reference 2\">↩</a></p>\n\
+             </li>\n</ol>\n</section>\n"
+        );
+    }
+
+    #[test]
+    fn footnotes_without_definitions() {
+        assert_eq!(render("No notes here.\n"), "<p>No notes here.</p>\n");
+    }
+}
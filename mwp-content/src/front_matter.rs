@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::{Date, macros::format_description};
+
+/// Metadata declared in a leading YAML (`---`) or TOML (`+++`) block of a page.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrontMatter {
+    /// Title that takes precedence over the first heading of the page.
+    pub title: Option<String>,
+
+    /// Tags that take precedence over the ones derived from the page directory.
+    pub tags: Vec<String>,
+
+    /// Alternative names the page can be referred to by.
+    pub aliases: Vec<String>,
+
+    /// Date the page was written or last meaningfully updated.
+    #[serde(
+        serialize_with = "serialize_date",
+        deserialize_with = "deserialize_date"
+    )]
+    pub date: Option<Date>,
+
+    /// Sort order among siblings, lower weights come first.
+    pub weight: Option<i64>,
+
+    /// Whether the page is a work in progress.
+    pub draft: bool,
+
+    /// Short summary of the page.
+    pub description: Option<String>,
+
+    /// Any other keys present in the front matter.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Yaml,
+    Toml,
+}
+
+/// Splits a leading front matter block off the page content.
+///
+/// Returns the parsed front matter, if any, and the remaining markdown, or a
+/// description of why the front matter block is invalid.
+///
+/// A `---` block that isn't a YAML mapping is most likely a page starting
+/// with a thematic break, it's left in the markdown and described in
+/// `warnings` instead.
+pub(crate) fn split<'c>(
+    content: &'c str,
+    warnings: &mut Vec<String>,
+) -> Result<(Option<FrontMatter>, &'c str), String> {
+    let Some((format, raw, body)) = find_block(content) else {
+        return Ok((None, content));
+    };
+
+    let parsed = match format {
+        Format::Yaml if raw.trim().is_empty() => Ok(FrontMatter::default()),
+        Format::Yaml => match serde_yaml::from_str::<serde_yaml::Value>(raw) {
+            Ok(value @ serde_yaml::Value::Mapping(_)) => {
+                serde_yaml::from_value::<FrontMatter>(value).map_err(|err| err.to_string())
+            }
+            Ok(_) => {
+                warnings.push(
+                    "leading `---` block isn't a YAML mapping, not treating it as front matter"
+                        .into(),
+                );
+                return Ok((None, content));
+            }
+            Err(err) => {
+                warnings.push(format!(
+                    "leading `---` block isn't valid YAML, not treating it as front matter: {err}"
+                ));
+                return Ok((None, content));
+            }
+        },
+        Format::Toml => toml::from_str::<FrontMatter>(raw).map_err(|err| err.to_string()),
+    }?;
+
+    Ok((Some(parsed), body))
+}
+
+fn find_block(content: &str) -> Option<(Format, &str, &str)> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let (first, rest) = split_line(content)?;
+    let (format, fence) = match first.trim_end() {
+        "---" => (Format::Yaml, "---"),
+        "+++" => (Format::Toml, "+++"),
+        _ => return None,
+    };
+
+    let mut offset = 0;
+    let mut remaining = rest;
+    while let Some((line, tail)) = split_line(remaining) {
+        if line.trim_end() == fence {
+            return Some((format, &rest[..offset], tail));
+        }
+        offset += remaining.len() - tail.len();
+        remaining = tail;
+    }
+
+    None
+}
+
+/// Splits off the first line, returning `None` once the input is exhausted.
+fn split_line(s: &str) -> Option<(&str, &str)> {
+    if s.is_empty() {
+        return None;
+    }
+
+    Some(match s.find('\n') {
+        Some(idx) => (&s[..idx], &s[idx + 1..]),
+        None => (s, ""),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDate {
+    Text(String),
+    Toml(toml::value::Datetime),
+}
+
+fn serialize_date<S>(date: &Option<Date>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let date = date
+        .map(|date| date.format(format_description!("[year]-[month]-[day]")))
+        .transpose()
+        .map_err(serde::ser::Error::custom)?;
+    date.serialize(serializer)
+}
+
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<Date>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = match Option::<RawDate>::deserialize(deserializer)? {
+        Some(RawDate::Text(text)) => text,
+        Some(RawDate::Toml(datetime)) => datetime.to_string(),
+        None => return Ok(None),
+    };
+
+    let date = raw.get(..10).unwrap_or(&raw);
+    Date::parse(date, format_description!("[year]-[month]-[day]"))
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn split_yaml() {
+        let (meta, body) = split(
+            "---\ntitle: Git\ntags: [tools, vcs]\ndate: 2024-03-01\nweight: 2\nsource: book\n---\n# Body\n",
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let meta = meta.unwrap();
+
+        assert_eq!(meta.title.as_deref(), Some("Git"));
+        assert_eq!(meta.tags, vec!["tools", "vcs"]);
+        assert_eq!(meta.date, Some(date!(2024 - 03 - 01)));
+        assert_eq!(meta.weight, Some(2));
+        assert_eq!(meta.extra.get("source"), Some(&"book".into()));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn split_toml() {
+        let (meta, body) = split(
+            "+++\ntitle = \"Rust\"\naliases = [\"rustlang\"]\ndate = 2023-12-24T10:00:00Z\ndraft = true\n+++\ntext",
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let meta = meta.unwrap();
+
+        assert_eq!(meta.title.as_deref(), Some("Rust"));
+        assert_eq!(meta.aliases, vec!["rustlang"]);
+        assert_eq!(meta.date, Some(date!(2023 - 12 - 24)));
+        assert!(meta.draft);
+        assert_eq!(body, "text");
+    }
+
+    #[test]
+    fn split_without_front_matter() {
+        let content = "# Title\n\n---\n\nafter a rule";
+        assert_eq!(split(content, &mut Vec::new()), Ok((None, content)));
+    }
+
+    #[test]
+    fn split_thematic_break() {
+        let mut warnings = Vec::new();
+        let content = "---\nA page opening with a rule.\n\n---\n\nMore text.\n";
+        assert_eq!(split(content, &mut warnings), Ok((None, content)));
+        assert_eq!(warnings.len(), 1);
+
+        let content = "---\n- [ ] todo: [unclosed\n---\n";
+        assert_eq!(split(content, &mut warnings), Ok((None, content)));
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        let (meta, _) = split(
+            "---\ntitle: Git\ndate: 2024-03-01\nsource: book\n---\n",
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let meta = meta.unwrap();
+        let json = serde_json::to_string(&meta).unwrap();
+        assert!(json.contains(r#""date":"2024-03-01""#), "{json}");
+        assert_eq!(serde_json::from_str::<FrontMatter>(&json).unwrap(), meta);
+    }
+
+    #[test]
+    fn split_invalid() {
+        assert!(split("---\ndate: yesterday\n---\n", &mut Vec::new()).is_err());
+        assert!(split("+++\ntags = [unclosed\n+++\n", &mut Vec::new()).is_err());
+    }
+}
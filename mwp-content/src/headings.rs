@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+/// A heading of a page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    /// Level of the heading, 1 for `#` up to 6 for `######`.
+    pub level: u8,
+
+    /// Plain text of the heading.
+    pub text: String,
+
+    /// Anchor id of the heading, unique within the page.
+    pub id: String,
+}
+
+/// Turns heading text into an anchor id, following the GitHub conventions.
+///
+/// Letters and digits are lowercased, spaces become dashes, and everything
+/// else except dashes and underscores is dropped.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c.to_lowercase().next().unwrap_or(c)),
+            ' ' | '-' => Some('-'),
+            '_' => Some('_'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hands out unique anchor ids by suffixing repeated slugs with `-1`, `-2`, ...
+#[derive(Debug, Default)]
+pub(crate) struct Slugger {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let count = self.seen.entry(base.clone()).or_insert(0);
+        let id = if *count == 0 {
+            base
+        } else {
+            format!("{base}-{count}")
+        };
+        *count += 1;
+        id
+    }
+}
+
+/// Sets the anchor ids of heading events and appends a `#` link to each of them.
+///
+/// `headings` must be the outline collected from the same events.
+pub(crate) fn anchor_headings<'a>(events: Vec<Event<'a>>, headings: &[Heading]) -> Vec<Event<'a>> {
+    let mut ids = headings.iter().map(|heading| heading.id.as_str());
+    let mut current = None;
+    let mut anchored = Vec::with_capacity(events.len() + headings.len());
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading {
+                level,
+                classes,
+                attrs,
+                ..
+            }) => {
+                current = ids.next();
+                anchored.push(Event::Start(Tag::Heading {
+                    level,
+                    id: current.map(|id| id.to_string().into()),
+                    classes,
+                    attrs,
+                }));
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                if let Some(id) = current.take() {
+                    anchored.push(Event::InlineHtml(
+                        format!(
+                            "<a class=\"anchor\" href=\"#{id}\" aria-label=\"Link to this section\">#</a>"
+                        )
+                        .into(),
+                    ));
+                }
+                anchored.push(Event::End(TagEnd::Heading(level)));
+            }
+            event => anchored.push(event),
+        }
+    }
+
+    anchored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_headings() {
+        assert_eq!(slugify("Getting Started"), "getting-started");
+        assert_eq!(slugify("What's `git rebase`?"), "whats-git-rebase");
+        assert_eq!(slugify("snake_case & more"), "snake_case--more");
+        assert_eq!(slugify("Čeština"), "čeština");
+    }
+
+    #[test]
+    fn slugger_dedupes() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Usage"), "usage");
+        assert_eq!(slugger.slug("Usage"), "usage-1");
+        assert_eq!(slugger.slug("Usage"), "usage-2");
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::OnceLock;
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+use syntect::{
+    highlighting::ThemeSet,
+    html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Highlighted code uses classes instead of inline styles so that the theme
+/// can be picked, and switched for dark mode, by the stylesheet.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the built-in highlighting themes.
+pub fn theme_names() -> Vec<&'static str> {
+    themes().themes.keys().map(String::as_str).collect()
+}
+
+/// Stylesheet for highlighted code blocks using the named theme, `None` if
+/// there is no such theme.
+pub fn theme_css(name: &str) -> Option<String> {
+    let theme = themes().themes.get(name)?;
+    css_for_theme_with_class_style(theme, CLASS_STYLE).ok()
+}
+
+struct CodeBlock {
+    language: String,
+    syntax: &'static SyntaxReference,
+    code: String,
+}
+
+/// Replaces fenced code blocks in a known language with highlighted HTML.
+///
+/// Blocks without a language, or in a language there's no syntax for, are
+/// left for the regular renderer.
+pub(crate) fn highlight_code_blocks<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut block: Option<CodeBlock> = None;
+
+    for event in events {
+        if let Some(current) = block.as_mut() {
+            match event {
+                Event::Text(text) => current.code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => {
+                    let current = block.take().expect("open code block");
+                    output.push(Event::Html(render(&current).into()));
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let language = info.split_whitespace().next().unwrap_or_default();
+                match syntaxes().find_syntax_by_token(language) {
+                    Some(syntax) if !language.is_empty() => {
+                        block = Some(CodeBlock {
+                            language: language.to_string(),
+                            syntax,
+                            code: String::new(),
+                        });
+                    }
+                    _ => output.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))),
+                }
+            }
+            event => output.push(event),
+        }
+    }
+
+    output
+}
+
+fn render(block: &CodeBlock) -> String {
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(block.syntax, syntaxes(), CLASS_STYLE);
+    let highlighted = LinesWithEndings::from(&block.code)
+        .try_for_each(|line| generator.parse_html_for_line_which_includes_newline(line));
+
+    let mut html = String::from("<pre class=\"hl-code\"><code class=\"language-");
+    escape_html(&mut html, &block.language).expect("write to string");
+    html.push_str("\">");
+    match highlighted {
+        Ok(()) => html.push_str(&generator.finalize()),
+        Err(_) => escape_html(&mut html, &block.code).expect("write to string"),
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{Parser, html};
+
+    use super::*;
+
+    fn render_markdown(markdown: &str) -> String {
+        let events = Parser::new(markdown).collect();
+        let mut output = String::new();
+        html::push_html(&mut output, highlight_code_blocks(events).into_iter());
+        output
+    }
+
+    #[test]
+    fn highlight_known_languages() {
+        let html = render_markdown("```rust\nfn main() {}\n```\n");
+        assert!(html.starts_with("<pre class=\"hl-code\"><code class=\"language-rust\">"));
+        assert!(html.contains("<span class=\"hl-storage hl-type hl-function hl-rust\">fn</span>"));
+
+        assert_eq!(
+            render_markdown("```nope\n<b>\n```\n"),
+            "<pre><code class=\"language-nope\">&lt;b&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn theme_stylesheets() {
+        assert!(theme_css("InspiredGitHub").unwrap().contains(".hl-code {"));
+        assert!(theme_css("nope").is_none());
+    }
+}
@@ -1,24 +1,56 @@
+//! Loads the markdown pages of a wiki and renders them to HTML.
+//!
+//! Links found on a page are kept in three lists, one per kind, since each
+//! kind carries different data and is consumed differently:
+//!
+//! - [`Page::links`] are [`Link`]s to remote `http(s)` pages, with the
+//!   title, starred flag and tags the search index shows them with.
+//! - [`Page::internal_links`] are [`InternalLink`]s, relative markdown links
+//!   already resolved to a page path while parsing.
+//! - [`Page::wiki_links`] are [`WikiLink`]s, `[[Target]]` links that name a
+//!   page by title or alias and are only resolved once every page is loaded,
+//!   by [`Content`].
+//!
+//! [`Page::linked_pages`] merges the last two into the paths of the linked
+//! pages, and [`Content::remote_links`] collects the first across the wiki.
+
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Write,
-    fs,
+    fs, io,
     path::{Path, PathBuf},
 };
 
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, html};
-use url::Url;
-use walkdir::WalkDir;
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Link {
-    pub title: String,
-    pub url: Url,
-    pub starred: bool,
-    pub tags: Vec<String>,
-}
+use pulldown_cmark::{Event, HeadingLevel, LinkType, Parser, Tag, TagEnd, html};
+use serde::{Deserialize, Serialize};
+
+mod callouts;
+mod diagrams;
+mod embeds;
+mod error;
+mod footnotes;
+mod front_matter;
+mod headings;
+mod highlight;
+mod links;
+mod markdown;
+mod math;
+mod walk;
+mod wikilink;
+
+pub use diagrams::rendering_diagram;
+pub use embeds::Embed;
+pub use error::Error;
+pub use front_matter::FrontMatter;
+pub use headings::{Heading, slugify};
+pub use highlight::{theme_css, theme_names};
+pub use links::{InternalLink, Link};
+pub use markdown::MarkdownOptions;
+pub use walk::{DEFAULT_IGNORED, IGNORE_FILE, Walker};
+pub use wikilink::{UnresolvedLink, WikiLink, page_url};
 
 /// Represents a single page of content in the wiki.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Page {
     /// The title of the page.
     pub title: String,
@@ -40,122 +72,352 @@ pub struct Page {
 
     /// A list of links found on the page in (anchor text, url) format.
     pub links: Vec<Link>,
+
+    /// A list of `[[wiki links]]` found on the page, in document order.
+    pub wiki_links: Vec<WikiLink>,
+
+    /// A list of `![[embeds]]` found on the page, in document order.
+    pub embeds: Vec<Embed>,
+
+    /// Root-relative paths of local files, such as images, the page refers to.
+    pub assets: Vec<String>,
+
+    /// A list of relative markdown links and heading anchors found on the page.
+    pub internal_links: Vec<InternalLink>,
+
+    /// Outline of the page headings, in document order.
+    pub headings: Vec<Heading>,
+
+    /// Metadata from the front matter block, empty if the page has none.
+    pub meta: FrontMatter,
+
+    /// Problems found while rendering the page, such as malformed formulas.
+    pub warnings: Vec<String>,
 }
 
 impl Page {
-    // From parses a page content.
-    pub fn from<P: AsRef<Path>>(file_name: P, content: String) -> Self {
+    // From parses a page content with the default markdown options.
+    pub fn from<P: AsRef<Path>>(file_name: P, content: String) -> Result<Self, Error> {
+        Self::parse(file_name, content, &MarkdownOptions::default())
+    }
+
+    /// Parses a page content with the given markdown extensions enabled.
+    pub fn parse<P: AsRef<Path>>(
+        file_name: P,
+        content: String,
+        options: &MarkdownOptions,
+    ) -> Result<Self, Error> {
         let mut clean_path = file_name.as_ref();
+        let source_dir = clean_path
+            .parent()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|| "/".into());
         if clean_path
             .file_name()
             .is_some_and(|name| name == "index" || name == "README")
         {
-            clean_path = clean_path.parent().unwrap()
+            clean_path = clean_path.parent().unwrap_or(clean_path)
         }
+        let page_path = clean_path.display().to_string();
 
-        let tags = clean_path
+        let mut warnings = Vec::new();
+        let (meta, content) =
+            front_matter::split(&content, &mut warnings).map_err(|message| Error::FrontMatter {
+                path: file_name.as_ref().to_path_buf(),
+                message,
+            })?;
+        let meta = meta.unwrap_or_default();
+
+        let path_tags = clean_path
             .iter()
-            .filter_map(|component| {
-                if *component == Path::new("/") {
-                    None
-                } else {
-                    Some(component.to_str().unwrap().into())
-                }
+            .filter(|component| *component != Path::new("/"))
+            .map(|component| {
+                component
+                    .to_str()
+                    .map(String::from)
+                    .ok_or_else(|| Error::InvalidPath {
+                        path: file_name.as_ref().to_path_buf(),
+                        reason: "page paths must be valid UTF-8",
+                    })
             })
-            .collect::<Vec<String>>();
-
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
+            .collect::<Result<Vec<String>, Error>>()?;
+        let tags = if meta.tags.is_empty() {
+            derive_tags(file_name.as_ref())
+        } else {
+            meta.tags.clone()
+        };
 
         let mut text_output = String::new();
 
         let mut page_title = String::new();
         let mut link_title = String::new();
         let mut links: Vec<Link> = Vec::new();
+        let mut wiki_links: Vec<WikiLink> = Vec::new();
+        let mut embeds: Vec<Embed> = Vec::new();
+        let mut assets: Vec<String> = Vec::new();
+        let mut embedding = false;
+        let mut internal_links: Vec<InternalLink> = Vec::new();
+        let mut headings: Vec<Heading> = Vec::new();
+        let mut heading: Option<(u8, String)> = None;
+        let mut slugger = headings::Slugger::default();
         let mut open_tags: Vec<Tag> = Vec::new();
 
         #[allow(clippy::unnecessary_filter_map)]
-        let parser = Parser::new_ext(&content, options).filter_map(|event| match event.clone() {
-            Event::Text(text) => {
-                if page_title.is_empty()
-                    && open_tags.last().is_some_and(|tag| {
-                        matches!(
-                            tag,
-                            Tag::Heading {
-                                level: HeadingLevel::H1,
-                                ..
-                            }
-                        )
-                    })
-                {
-                    page_title = text.to_string();
+        let parser = Parser::new_ext(content, options.parser_options()).filter_map(|event| {
+            match event.clone() {
+                // The target of an embed is written as its alt text.
+                Event::End(TagEnd::Image) if embedding => {
+                    embedding = false;
+                    None
+                }
+                _ if embedding => None,
+                Event::Start(Tag::Image {
+                    link_type: LinkType::WikiLink { .. },
+                    dest_url,
+                    ..
+                }) if embeds::is_page_target(&dest_url) => {
+                    embeds.push(Embed::new(&dest_url));
+                    embedding = true;
+                    Some(Event::InlineHtml(
+                        embeds::placeholder(embeds.len() - 1).into(),
+                    ))
                 }
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }) => {
+                    let dest_url = match links::rewrite(&source_dir, &dest_url) {
+                        Some(rewritten) => {
+                            assets.extend(rewritten.asset);
+                            rewritten.href.into()
+                        }
+                        None => dest_url,
+                    };
+                    let tag = Tag::Image {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    };
+                    open_tags.push(tag.clone());
+                    Some(Event::Start(tag))
+                }
+                Event::Text(text) => {
+                    text_output.push_str(&text);
+                    if page_title.is_empty()
+                        && open_tags.last().is_some_and(|tag| {
+                            matches!(
+                                tag,
+                                Tag::Heading {
+                                    level: HeadingLevel::H1,
+                                    ..
+                                }
+                            )
+                        })
+                    {
+                        page_title = text.to_string();
+                    }
+
+                    if open_tags.iter().any(|tag| matches!(tag, Tag::Link { .. })) {
+                        link_title.push_str(&text);
+                    }
+
+                    if let Some((_, heading_text)) = heading.as_mut() {
+                        heading_text.push_str(&text);
+                    }
 
-                if open_tags
-                    .last()
-                    .is_some_and(|tag| matches!(tag, Tag::Link { .. }))
-                {
-                    link_title = text.to_string();
+                    Some(event)
                 }
+                Event::Code(code) => {
+                    text_output.push_str(&code);
+                    if open_tags.iter().any(|tag| matches!(tag, Tag::Link { .. })) {
+                        link_title.push_str(&code);
+                    }
 
-                Some(event)
-            }
-            Event::Start(tag) => {
-                open_tags.push(tag);
-                Some(event)
-            }
-            Event::End(TagEnd::Heading(..) | TagEnd::Paragraph | TagEnd::Item) => {
-                writeln!(&mut text_output).expect("write text output");
-                open_tags.pop();
-                Some(event)
-            }
-            Event::End(TagEnd::Link) => {
-                if let Some(Tag::Link { dest_url, .. }) = open_tags.pop() {
-                    if let Ok(url) = Url::parse(&dest_url) {
-                        links.push(Link {
-                            title: link_title.clone(),
-                            url,
-                            starred: open_tags.iter().any(|tag| matches!(tag, Tag::Strong)),
-                            tags: tags.clone(),
+                    if let Some((_, heading_text)) = heading.as_mut() {
+                        heading_text.push_str(&code);
+                    }
+                    Some(event)
+                }
+                Event::Start(tag @ Tag::Heading { level, .. }) => {
+                    heading = Some((level as u8, String::new()));
+                    open_tags.push(tag);
+                    Some(event)
+                }
+                Event::Start(
+                    ref tag @ Tag::Link {
+                        link_type: LinkType::WikiLink { .. },
+                        ref dest_url,
+                        ..
+                    },
+                ) => {
+                    wiki_links.push(WikiLink::new(dest_url));
+                    link_title.clear();
+                    open_tags.push(tag.clone());
+                    Some(Event::InlineHtml(
+                        wikilink::placeholder(wiki_links.len() - 1).into(),
+                    ))
+                }
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }) if dest_url.starts_with('#') => {
+                    link_title.clear();
+                    internal_links.push(InternalLink {
+                        path: page_path.clone(),
+                        fragment: Some(dest_url[1..].to_string()),
+                    });
+                    open_tags.push(Tag::Link {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    });
+                    Some(event)
+                }
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }) => {
+                    link_title.clear();
+                    let dest_url = match links::rewrite(&source_dir, &dest_url) {
+                        Some(rewritten) => {
+                            internal_links.extend(rewritten.page);
+                            assets.extend(rewritten.asset);
+                            rewritten.href.into()
+                        }
+                        None => dest_url,
+                    };
+                    let tag = Tag::Link {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    };
+                    open_tags.push(tag.clone());
+                    Some(Event::Start(tag))
+                }
+                Event::Start(tag) => {
+                    open_tags.push(tag);
+                    Some(event)
+                }
+                Event::End(TagEnd::Heading(..) | TagEnd::Paragraph | TagEnd::Item) => {
+                    if let Some((level, text)) = heading.take() {
+                        headings.push(Heading {
+                            level,
+                            id: slugger.slug(&text),
+                            text,
                         });
+                    }
+                    writeln!(&mut text_output).expect("write text output");
+                    open_tags.pop();
+                    Some(event)
+                }
+                Event::End(TagEnd::Link) => match open_tags.pop() {
+                    Some(Tag::Link {
+                        link_type: LinkType::WikiLink { .. },
+                        ..
+                    }) => {
+                        if let Some(link) = wiki_links.last_mut() {
+                            link.label = std::mem::take(&mut link_title);
+                        }
+                        Some(Event::InlineHtml("</a>".into()))
+                    }
+                    Some(Tag::Link { dest_url, .. }) => {
+                        if let Some(link) = Link::remote(
+                            &dest_url,
+                            &link_title,
+                            open_tags.iter().any(|tag| matches!(tag, Tag::Strong)),
+                            &tags,
+                            &page_path,
+                        ) {
+                            links.push(link);
+                        }
                         link_title.clear();
+                        Some(event)
                     }
+                    _ => Some(event),
+                },
+                Event::End(_) => {
+                    open_tags.pop();
+                    Some(event)
                 }
-                Some(event)
+                event => Some(event),
             }
-            event => Some(event),
         });
+        let events: Vec<Event> = parser.collect();
 
         let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
+        let mut events = headings::anchor_headings(events, &headings);
+        if !embeds.is_empty() {
+            events = embeds::unwrap_paragraphs(events);
+        }
+        if options.callouts {
+            events = callouts::render_callouts(events);
+        }
+        if options.math {
+            events = math::render_math(events, &mut warnings);
+        }
+        if options.diagrams {
+            events = diagrams::render_diagrams(events, options.cache_dir.as_deref(), &mut warnings);
+        }
+        if options.highlight {
+            events = highlight::highlight_code_blocks(events);
+        }
+        html::push_html(
+            &mut html_output,
+            footnotes::collect_footnotes(events).into_iter(),
+        );
 
-        let mut parents: Vec<String> = Vec::with_capacity(tags.len());
-        let mut link = String::with_capacity(clean_path.display().to_string().len());
+        let mut parents: Vec<String> = Vec::with_capacity(path_tags.len());
+        let mut link = String::with_capacity(page_path.len());
 
         parents.push("/".into());
-        for parent in tags.iter() {
+        for parent in path_tags.iter() {
             link.push('/');
             link.push_str(parent.as_str());
             parents.push(link.clone());
         }
 
-        let clean_path = clean_path.display().to_string();
-
-        Page {
-            title: page_title,
-            tags: tags.clone(),
+        Ok(Page {
+            title: meta.title.clone().unwrap_or(page_title),
+            tags,
             links,
+            wiki_links,
+            embeds,
+            assets,
+            internal_links,
+            headings,
             html: html_output,
             text: text_output,
-            path: clean_path,
+            path: page_path,
             parents,
-        }
+            meta,
+            warnings,
+        })
+    }
+
+    /// Paths of the wiki pages this page links to.
+    pub fn linked_pages(&self) -> impl Iterator<Item = &str> {
+        self.wiki_links
+            .iter()
+            .filter_map(|link| link.resolved.as_deref())
+            .chain(self.internal_links.iter().map(|link| link.path.as_str()))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Content {
     pages: HashMap<String, Page>,
+    names: HashMap<String, String>,
+    unresolved: Vec<UnresolvedLink>,
+    backlinks: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug)]
@@ -166,37 +428,60 @@ pub struct Node {
 }
 
 impl Content {
-    pub async fn from_dir(src: &str) -> Self {
-        let mut pages = HashMap::new();
-
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-
-        for entry in WalkDir::new(src)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| !e.file_type().is_dir())
-            .filter(|e| {
-                e.path()
-                    .extension()
-                    .is_some_and(|ext| ext.to_str() == Some("md"))
-            })
-        {
-            let contents =
-                fs::read_to_string(entry.path()).expect("Something went wrong reading the file");
+    /// Loads all markdown pages under `src`, failing on the first file that
+    /// can't be loaded.
+    pub async fn from_dir(src: &str) -> Result<Self, Error> {
+        Self::from_walker(&Walker::new(src), &MarkdownOptions::default()).await
+    }
 
-            let file_name = remove_extension(
-                Path::new("/")
-                    .join(entry.path().strip_prefix(src).unwrap())
-                    .as_path(),
-            );
+    /// Loads all markdown pages under `src`, skipping files that can't be
+    /// loaded and returning their errors alongside the content.
+    pub async fn from_dir_lenient(src: &str) -> (Self, Vec<Error>) {
+        Self::from_walker_lenient(&Walker::new(src), &MarkdownOptions::default()).await
+    }
 
-            let page = Page::from(&file_name, contents);
+    /// Loads all markdown pages the walker yields, failing on the first file
+    /// that can't be loaded.
+    pub async fn from_walker(walker: &Walker, options: &MarkdownOptions) -> Result<Self, Error> {
+        let pages = load_pages(walker, options).collect::<Result<Vec<Page>, Error>>()?;
+        Ok(Self::from_pages(pages))
+    }
 
-            pages.insert(page.path.clone(), page);
+    /// Loads all markdown pages the walker yields, skipping files that can't
+    /// be loaded and returning their errors alongside the content.
+    pub async fn from_walker_lenient(
+        walker: &Walker,
+        options: &MarkdownOptions,
+    ) -> (Self, Vec<Error>) {
+        let mut pages = Vec::new();
+        let mut errors = Vec::new();
+        for page in load_pages(walker, options) {
+            match page {
+                Ok(page) => pages.push(page),
+                Err(err) => errors.push(err),
+            }
         }
+        (Self::from_pages(pages), errors)
+    }
 
-        Self { pages }
+    /// Builds content from already parsed pages and resolves links between them.
+    pub fn from_pages<I: IntoIterator<Item = Page>>(pages: I) -> Self {
+        let pages: HashMap<String, Page> = pages
+            .into_iter()
+            .map(|page| (page.path.clone(), page))
+            .collect();
+        let names = wikilink::build_names(&pages);
+
+        let mut content = Self {
+            pages,
+            names,
+            unresolved: Vec::new(),
+            backlinks: HashMap::new(),
+        };
+        content.resolve_wiki_links();
+        content.expand_embeds();
+        content.backlinks = build_backlinks(&content.pages);
+        content
     }
 
     pub fn build_tree(&self) -> Vec<Node> {
@@ -218,6 +503,19 @@ impl Content {
         self.pages.get(path)
     }
 
+    /// Pages that link to the page at `path`, sorted by title.
+    pub fn backlinks(&self, path: &str) -> Vec<&Page> {
+        let mut pages: Vec<&Page> = self
+            .backlinks
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|source| self.pages.get(source))
+            .collect();
+        pages.sort_by_key(|p| p.title.as_str());
+        pages
+    }
+
     // TODO: replace with iterator
     pub fn all(&self) -> &HashMap<String, Page> {
         &self.pages
@@ -232,10 +530,24 @@ impl Content {
             .filter(|p| p.path.starts_with(path)) // match only files under path
             .filter(|p| path_depth(&p.path) == target_depth) // match only first layer under path
             .collect();
-        pages.sort_by_key(|p| p.title.as_str());
+        pages.sort_by_key(|p| (p.meta.weight.is_none(), p.meta.weight, p.title.as_str()));
         pages
     }
 
+    /// Remote links of all pages, each URL once, in page path order.
+    pub fn remote_links(&self) -> Vec<Link> {
+        let mut pages: Vec<&Page> = self.pages.values().collect();
+        pages.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut seen = HashSet::new();
+        pages
+            .into_iter()
+            .flat_map(|page| page.links.iter())
+            .filter(|link| seen.insert(link.url.clone()))
+            .cloned()
+            .collect()
+    }
+
     pub fn keys(&self) -> Vec<String> {
         self.pages.keys().cloned().collect()
     }
@@ -245,6 +557,89 @@ impl Content {
     }
 }
 
+/// Derives tags from the directories a page's source file is in, falling back
+/// to the file name for top-level pages.
+fn derive_tags(file_name: &Path) -> Vec<String> {
+    let is_tag = |name: &&str| !name.eq_ignore_ascii_case("index") && *name != "README";
+    let mut tags: Vec<String> = file_name
+        .parent()
+        .into_iter()
+        .flat_map(Path::iter)
+        .filter_map(|component| component.to_str())
+        .filter(|name| *name != "/")
+        .filter(is_tag)
+        .map(|name| name.replace('_', " "))
+        .collect();
+    if tags.is_empty()
+        && let Some(stem) = file_name.file_stem().and_then(|stem| stem.to_str())
+        && is_tag(&stem)
+    {
+        tags.push(stem.replace('_', " "));
+    }
+    tags
+}
+
+fn load_pages<'a>(
+    walker: &'a Walker,
+    options: &'a MarkdownOptions,
+) -> impl Iterator<Item = Result<Page, Error>> + 'a {
+    walker
+        .markdown_files()
+        .map(|path| load_page(walker.root(), &path?, options))
+}
+
+/// Loads and parses the markdown file at `path`, which must be under `root`.
+///
+/// The page isn't linked to any others until it's part of [`Content`].
+pub fn load_page(root: &Path, path: &Path, options: &MarkdownOptions) -> Result<Page, Error> {
+    let contents = fs::read_to_string(path).map_err(|source| {
+        if source.kind() == io::ErrorKind::InvalidData {
+            Error::InvalidUtf8 {
+                path: path.to_path_buf(),
+            }
+        } else {
+            Error::Read {
+                path: path.to_path_buf(),
+                source,
+            }
+        }
+    })?;
+
+    let relative = path.strip_prefix(root).map_err(|_| Error::InvalidPath {
+        path: path.to_path_buf(),
+        reason: "file is outside of the content directory",
+    })?;
+    let file_name = remove_extension(Path::new("/").join(relative).as_path());
+
+    Page::parse(&file_name, contents, options).map_err(|err| match err {
+        Error::FrontMatter { message, .. } => Error::FrontMatter {
+            path: path.to_path_buf(),
+            message,
+        },
+        Error::InvalidPath { reason, .. } => Error::InvalidPath {
+            path: path.to_path_buf(),
+            reason,
+        },
+        err => err,
+    })
+}
+
+fn build_backlinks(pages: &HashMap<String, Page>) -> HashMap<String, Vec<String>> {
+    let mut backlinks: HashMap<String, Vec<String>> = HashMap::new();
+    for page in pages.values() {
+        for target in page.linked_pages() {
+            if target == page.path {
+                continue;
+            }
+            let sources = backlinks.entry(target.to_string()).or_default();
+            if !sources.contains(&page.path) {
+                sources.push(page.path.clone());
+            }
+        }
+    }
+    backlinks
+}
+
 #[inline(always)]
 fn path_depth(s: &str) -> usize {
     s.chars().filter(|c| *c == '/').count()
@@ -253,8 +648,7 @@ fn path_depth(s: &str) -> usize {
 #[inline(always)]
 fn remove_extension(path: &Path) -> PathBuf {
     let mut new_path = PathBuf::new();
-    let parent_dir = path.parent().unwrap();
-    if let Some(file_name) = path.file_stem() {
+    if let (Some(parent_dir), Some(file_name)) = (path.parent(), path.file_stem()) {
         new_path.push(parent_dir);
         new_path.push(file_name);
     }
@@ -263,6 +657,8 @@ fn remove_extension(path: &Path) -> PathBuf {
 
 #[cfg(test)]
 mod tests {
+    use url::Url;
+
     use super::*;
 
     #[test]
@@ -282,7 +678,8 @@ Links from text should be [included](https://included.com).
 - **[starred](https://starred.com)**
             "#
             .into(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(
             page.links,
@@ -292,26 +689,270 @@ Links from text should be [included](https://included.com).
                     url: Url::parse("https://included.com").unwrap(),
                     starred: false,
                     tags: Vec::new(),
+                    source: "/".into(),
                 },
                 Link {
                     title: "test".into(),
                     url: Url::parse("https://test.com").unwrap(),
                     starred: false,
                     tags: Vec::new(),
+                    source: "/".into(),
                 },
                 Link {
                     title: "other".into(),
                     url: Url::parse("https://other.com").unwrap(),
                     starred: false,
                     tags: Vec::new(),
+                    source: "/".into(),
                 },
                 Link {
                     title: "starred".into(),
                     url: Url::parse("https://starred.com").unwrap(),
                     starred: true,
                     tags: Vec::new(),
+                    source: "/".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn page_from_front_matter() {
+        let page = Page::from(
+            "/tools/git",
+            "---\ntitle: Git Handbook\ntags: [vcs]\n---\n# Git\n\nBody text.\n".into(),
+        )
+        .unwrap();
+
+        assert_eq!(page.title, "Git Handbook");
+        assert_eq!(page.tags, vec!["vcs"]);
+        assert_eq!(page.parents, vec!["/", "/tools", "/tools/git"]);
+        assert!(!page.text.contains("title:"));
+        assert!(!page.html.contains("Git Handbook"));
+    }
+
+    #[test]
+    fn pages_under_path_weight() {
+        let content = Content::from_pages(
+            [
+                ("/a", "# Alpha\n"),
+                ("/b", "---\nweight: 2\n---\n# Beta\n"),
+                ("/c", "---\nweight: 1\n---\n# Gamma\n"),
+            ]
+            .into_iter()
+            .map(|(path, content)| Page::from(path, content.into()).unwrap()),
+        );
+
+        let titles: Vec<&str> = content
+            .pages_under_path("")
+            .iter()
+            .map(|p| p.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Gamma", "Beta", "Alpha"]);
+    }
+
+    #[test]
+    fn backlinks() {
+        let content = Content::from_pages(
+            [
+                ("/git", "# Git\n\n[[Git]] links to itself.\n"),
+                ("/jj", "# JJ\n\nCompare with [[Git]] and [[git|again]].\n"),
+                ("/tools", "# Tools\n\n- [[Git]]\n- [[JJ]]\n"),
+            ]
+            .into_iter()
+            .map(|(path, content)| Page::from(path, content.into()).unwrap()),
+        );
+
+        let titles = |path: &str| -> Vec<String> {
+            content
+                .backlinks(path)
+                .iter()
+                .map(|p| p.title.clone())
+                .collect()
+        };
+        assert_eq!(titles("/git"), vec!["JJ", "Tools"]);
+        assert_eq!(titles("/jj"), vec!["Tools"]);
+        assert!(titles("/tools").is_empty());
+    }
+
+    #[test]
+    fn page_from_relative_links() {
+        let page = Page::from(
+            "/notes/index",
+            "See [git](../tools/git.md#usage), [rust](./rust/) and [below](#below).\n".into(),
+        )
+        .unwrap();
+
+        assert!(page.html.contains(r#"href="/tools/git/#usage""#));
+        assert!(page.html.contains(r#"href="/notes/rust/""#));
+        assert_eq!(
+            page.internal_links,
+            vec![
+                InternalLink {
+                    path: "/tools/git".into(),
+                    fragment: Some("usage".into()),
+                },
+                InternalLink {
+                    path: "/notes/rust".into(),
+                    fragment: None,
+                },
+                InternalLink {
+                    path: "/notes".into(),
+                    fragment: Some("below".into()),
                 },
             ]
         );
+        assert!(page.links.is_empty());
+    }
+
+    #[test]
+    fn page_from_assets() {
+        let page = Page::from(
+            "/notes/git",
+            "![diagram](img/flow.png) [slides](../talks/git.pdf) ![[shot.jpg]] ![remote](https://example.com/a.png)\n".into(),
+        )
+        .unwrap();
+
+        assert!(
+            page.html
+                .contains(r#"<img src="/notes/img/flow.png" alt="diagram" />"#)
+        );
+        assert!(
+            page.html
+                .contains(r#"<img src="/notes/shot.jpg" alt="shot.jpg" />"#)
+        );
+        assert_eq!(
+            page.assets,
+            vec!["/notes/img/flow.png", "/talks/git.pdf", "/notes/shot.jpg"]
+        );
+    }
+
+    #[test]
+    fn page_from_headings() {
+        let page = Page::from(
+            "/git",
+            "# Git\n\n## Usage\n\n### `git rebase`\n\n## Usage\n".into(),
+        )
+        .unwrap();
+
+        let outline: Vec<(u8, &str, &str)> = page
+            .headings
+            .iter()
+            .map(|h| (h.level, h.text.as_str(), h.id.as_str()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (1, "Git", "git"),
+                (2, "Usage", "usage"),
+                (3, "git rebase", "git-rebase"),
+                (2, "Usage", "usage-1"),
+            ]
+        );
+        assert!(page.html.contains(
+            r##"<h2 id="usage-1">Usage<a class="anchor" href="#usage-1" aria-label="Link to this section">#</a></h2>"##
+        ));
+    }
+
+    #[test]
+    fn load_pages_reports_bad_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("tools")).unwrap();
+        fs::write(root.join("tools/git.md"), "# Git\n").unwrap();
+        fs::write(root.join("binary.md"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(root.join("broken.md"), "---\ndate: yesterday\n---\n").unwrap();
+
+        let mut loaded: Vec<Result<String, String>> =
+            load_pages(&Walker::new(root), &MarkdownOptions::default())
+                .map(|page| {
+                    page.map(|page| page.path).map_err(|err| {
+                        let name = err.path().file_name().unwrap().to_string_lossy();
+                        match err {
+                            Error::InvalidUtf8 { .. } => format!("utf8 {name}"),
+                            Error::FrontMatter { .. } => format!("front matter {name}"),
+                            err => err.to_string(),
+                        }
+                    })
+                })
+                .collect();
+        loaded.sort();
+
+        assert_eq!(
+            loaded,
+            vec![
+                Ok("/tools/git".to_string()),
+                Err("front matter broken.md".to_string()),
+                Err("utf8 binary.md".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn page_from_remote_links() {
+        let page = Page::from(
+            "/dev_tools/git",
+            "- [The *Git* book](https://git-scm.com/book)\n- [](https://github.com/git)\n- [mail](mailto:me@example.com)\n".into(),
+        )
+        .unwrap();
+
+        let links: Vec<(&str, &[String], &str)> = page
+            .links
+            .iter()
+            .map(|link| {
+                (
+                    link.title.as_str(),
+                    link.tags.as_slice(),
+                    link.source.as_str(),
+                )
+            })
+            .collect();
+        let tags = vec!["dev tools".to_string()];
+        assert_eq!(
+            links,
+            vec![
+                ("The Git book", tags.as_slice(), "/dev_tools/git"),
+                ("github.com", tags.as_slice(), "/dev_tools/git"),
+            ]
+        );
+
+        let other = Page::from("/rust", "[Git](https://github.com/git)\n".into()).unwrap();
+        let content = Content::from_pages([page, other]);
+        let urls: Vec<String> = content
+            .remote_links()
+            .into_iter()
+            .map(|link| format!("{} {}", link.source, link.url))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "/dev_tools/git https://git-scm.com/book",
+                "/dev_tools/git https://github.com/git",
+            ]
+        );
+    }
+
+    #[test]
+    fn page_parse_markdown_options() {
+        let markdown = "| a |\n|---|\n| b |\n\n- [x] done\n";
+        let full = Page::from("/gfm", markdown.into()).unwrap();
+        let plain = Page::parse(
+            "/gfm",
+            markdown.into(),
+            &MarkdownOptions {
+                tables: false,
+                tasklists: false,
+                ..MarkdownOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert!(full.html.contains("<table>"));
+        assert!(
+            full.html
+                .contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>")
+        );
+        assert!(!plain.html.contains("<table>"));
+        assert!(plain.html.contains("[x] done"));
     }
 }
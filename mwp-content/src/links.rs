@@ -0,0 +1,233 @@
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Link to a remote web page found on a page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    /// Anchor text of the link, or the domain if the link has no text.
+    pub title: String,
+
+    /// Target of the link.
+    pub url: Url,
+
+    /// Whether the link is emphasized with `**bold**`.
+    pub starred: bool,
+
+    /// Tags of the page the link was found on.
+    pub tags: Vec<String>,
+
+    /// Path of the page the link was found on.
+    pub source: String,
+}
+
+impl Link {
+    /// Builds a link from a destination, returning `None` unless it's an
+    /// absolute `http` or `https` URL.
+    pub(crate) fn remote(
+        destination: &str,
+        text: &str,
+        starred: bool,
+        tags: &[String],
+        source: &str,
+    ) -> Option<Self> {
+        let url = Url::parse(destination).ok()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+
+        let title = match text.trim() {
+            "" => url.domain().unwrap_or(url.as_str()).to_string(),
+            text => text.to_string(),
+        };
+
+        Some(Link {
+            title,
+            url,
+            starred,
+            tags: tags.to_vec(),
+            source: source.to_string(),
+        })
+    }
+}
+
+/// Link from a page to another page of the wiki.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InternalLink {
+    /// Path of the linked page.
+    pub path: String,
+
+    /// Heading on the linked page, without the leading `#`.
+    pub fragment: Option<String>,
+}
+
+/// Relative or root-relative link destination rewritten for the built site.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Rewritten {
+    /// Destination the link should point to in the built site.
+    pub href: String,
+
+    /// Page the link points to, `None` for links to other files.
+    pub page: Option<InternalLink>,
+
+    /// Root-relative path of the file the link points to, percent-decoded,
+    /// `None` for links to pages.
+    pub asset: Option<String>,
+}
+
+/// Rewrites a link destination written in a page whose source file lives in
+/// `source_dir` (e.g. `/tools` for `/tools/git.md`).
+///
+/// Links to markdown files and directories become the `/path/` URL the page is
+/// built at, links to other files become root-relative. Absolute URLs and
+/// fragment-only links are returned as `None`.
+pub(crate) fn rewrite(source_dir: &str, destination: &str) -> Option<Rewritten> {
+    if destination.is_empty()
+        || destination.starts_with('#')
+        || destination.starts_with('?')
+        || destination.starts_with("//")
+        || Url::parse(destination).is_ok()
+    {
+        return None;
+    }
+
+    let (destination, fragment) = match destination.split_once('#') {
+        Some((destination, fragment)) => (destination, Some(fragment)),
+        None => (destination, None),
+    };
+    let destination = destination
+        .split_once('?')
+        .map_or(destination, |(path, _)| path);
+
+    let mut segments: Vec<&str> = Vec::new();
+    if !destination.starts_with('/') {
+        segments.extend(source_dir.split('/').filter(|s| !s.is_empty()));
+    }
+    for segment in destination.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let is_dir = matches!(destination.rsplit('/').next(), Some("" | "." | ".."));
+    let last = segments.last().copied().unwrap_or_default();
+    let is_page = is_dir || !has_file_extension(last);
+
+    if !is_page {
+        let mut href = format!("/{}", segments.join("/"));
+        let asset = percent_decode_str(&href).decode_utf8_lossy().into_owned();
+        if let Some(fragment) = fragment {
+            href.push('#');
+            href.push_str(fragment);
+        }
+        return Some(Rewritten {
+            href,
+            page: None,
+            asset: Some(asset),
+        });
+    }
+
+    if let Some(last) = segments.last_mut() {
+        *last = last.strip_suffix(".md").unwrap_or(last);
+        if matches!(*last, "index" | "README") {
+            segments.pop();
+        }
+    }
+
+    let encoded = format!("/{}", segments.join("/"));
+    let path = percent_decode_str(&encoded)
+        .decode_utf8_lossy()
+        .into_owned();
+    let mut href = crate::page_url(&encoded);
+    if let Some(fragment) = fragment {
+        href.push('#');
+        href.push_str(fragment);
+    }
+
+    Some(Rewritten {
+        href,
+        page: Some(InternalLink {
+            path,
+            fragment: fragment.map(str::to_string),
+        }),
+        asset: None,
+    })
+}
+
+/// Whether a file name ends in an extension other than `.md`, made of
+/// letters and digits with at least one letter, so names like `release-1.2`
+/// or `v2.0 notes` still refer to pages.
+pub(crate) fn has_file_extension(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(stem, extension)| {
+        !stem.is_empty()
+            && !extension.eq_ignore_ascii_case("md")
+            && extension.bytes().all(|b| b.is_ascii_alphanumeric())
+            && extension.bytes().any(|b| b.is_ascii_alphabetic())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn href(source_dir: &str, destination: &str) -> Option<String> {
+        rewrite(source_dir, destination).map(|rewritten| rewritten.href)
+    }
+
+    #[test]
+    fn rewrite_relative_links() {
+        assert_eq!(
+            href("/notes", "../tools/git.md").as_deref(),
+            Some("/tools/git/")
+        );
+        assert_eq!(href("/notes", "./rust/").as_deref(), Some("/notes/rust/"));
+        assert_eq!(
+            href("/notes", "git.md#usage").as_deref(),
+            Some("/notes/git/#usage")
+        );
+        assert_eq!(href("/notes", "../index.md").as_deref(), Some("/"));
+        assert_eq!(href("/", "/tools/README.md").as_deref(), Some("/tools/"));
+        assert_eq!(
+            href("/notes", "img/diagram.png").as_deref(),
+            Some("/notes/img/diagram.png")
+        );
+        assert_eq!(
+            rewrite("/notes", "../my%20slides.pdf#page=2").and_then(|rewritten| rewritten.asset),
+            Some("/my slides.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn rewrite_ignores_absolute_urls() {
+        assert_eq!(href("/notes", "https://example.com/a.md"), None);
+        assert_eq!(href("/notes", "mailto:me@example.com"), None);
+        assert_eq!(href("/notes", "#heading"), None);
+        assert_eq!(href("/notes", "//cdn.example.com/x.js"), None);
+    }
+
+    #[test]
+    fn rewrite_records_linked_page() {
+        assert_eq!(
+            rewrite("/notes", "../tools/git.md#usage").and_then(|r| r.page),
+            Some(InternalLink {
+                path: "/tools/git".into(),
+                fragment: Some("usage".into()),
+            })
+        );
+        assert_eq!(
+            rewrite("/notes", "My%20Notes.md").and_then(|r| r.page),
+            Some(InternalLink {
+                path: "/notes/My Notes".into(),
+                fragment: None,
+            })
+        );
+        assert_eq!(
+            href("/notes", "release-1.2").as_deref(),
+            Some("/notes/release-1.2/")
+        );
+    }
+}
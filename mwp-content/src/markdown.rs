@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use pulldown_cmark::Options;
+use serde::{Deserialize, Serialize};
+
+/// Markdown extensions and rendering features enabled when parsing pages.
+///
+/// Everything except math is enabled by default, giving a GitHub flavored set.
+/// Math is opt-in since it changes how `$` renders on existing pages. Wiki
+/// links are always on since the link graph depends on them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownOptions {
+    /// Pipe tables.
+    pub tables: bool,
+
+    /// `[^note]` footnotes, collected at the end of the page.
+    pub footnotes: bool,
+
+    /// `~~strikethrough~~` text.
+    pub strikethrough: bool,
+
+    /// `- [ ]` and `- [x]` task list items.
+    pub tasklists: bool,
+
+    /// Curly quotes, dashes and ellipses.
+    pub smart_punctuation: bool,
+
+    /// Syntax highlighting of fenced code blocks.
+    pub highlight: bool,
+
+    /// `$inline$` and `$$display$$` LaTeX formulas, rendered to MathML.
+    pub math: bool,
+
+    /// ` ```dot ` and ` ```mermaid ` blocks, rendered to inline SVG.
+    ///
+    /// Panics of the renderers are caught and turned into page warnings, but
+    /// the panic hook still runs, see [`crate::rendering_diagram`].
+    pub diagrams: bool,
+
+    /// `> [!note]` block quotes, rendered as callouts.
+    pub callouts: bool,
+
+    /// Directory rendered diagrams are cached in, set by the build rather
+    /// than the config file.
+    #[serde(skip)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: true,
+            highlight: true,
+            math: false,
+            diagrams: true,
+            callouts: true,
+            cache_dir: None,
+        }
+    }
+}
+
+impl MarkdownOptions {
+    pub(crate) fn parser_options(&self) -> Options {
+        let mut options = Options::ENABLE_WIKILINKS;
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options.set(Options::ENABLE_MATH, self.math);
+        options
+    }
+}
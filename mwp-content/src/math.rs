@@ -0,0 +1,98 @@
+use pulldown_cmark::Event;
+use pulldown_cmark_escape::escape_html;
+use pulldown_latex::{Parser, RenderConfig, Storage, config::DisplayMode, push_mathml};
+
+/// Renders `$inline$` and `$$display$$` math spans to MathML.
+///
+/// Formulas that can't be parsed are kept as their source in a
+/// `code.math-error` element and described in `warnings`.
+pub(crate) fn render_math<'a>(
+    events: Vec<Event<'a>>,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::InlineMath(latex) => {
+                Event::InlineHtml(render(&latex, DisplayMode::Inline, warnings).into())
+            }
+            Event::DisplayMath(latex) => {
+                Event::InlineHtml(render(&latex, DisplayMode::Block, warnings).into())
+            }
+            event => event,
+        })
+        .collect()
+}
+
+fn render(latex: &str, display_mode: DisplayMode, warnings: &mut Vec<String>) -> String {
+    let storage = Storage::new();
+    let events: Vec<_> = Parser::new(latex, &storage).collect();
+
+    let mut html = String::new();
+    if let Some(Err(err)) = events.iter().find(|event| event.is_err()) {
+        let reason = err.to_string();
+        warnings.push(format!(
+            "malformed formula {:?}: {}",
+            latex.trim(),
+            reason.lines().next().unwrap_or_default()
+        ));
+
+        let delimiter = match display_mode {
+            DisplayMode::Block => "$$",
+            DisplayMode::Inline => "$",
+        };
+        html.push_str("<code class=\"math-error\">");
+        html.push_str(delimiter);
+        escape_html(&mut html, latex).expect("write to string");
+        html.push_str(delimiter);
+        html.push_str("</code>");
+        return html;
+    }
+
+    let config = RenderConfig {
+        display_mode,
+        ..RenderConfig::default()
+    };
+    push_mathml(&mut html, events.into_iter(), config).expect("write to string");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{Options, Parser, html};
+
+    use super::*;
+
+    fn render_markdown(markdown: &str) -> (String, Vec<String>) {
+        let events = Parser::new_ext(markdown, Options::ENABLE_MATH).collect();
+        let mut warnings = Vec::new();
+        let mut output = String::new();
+        html::push_html(&mut output, render_math(events, &mut warnings).into_iter());
+        (output, warnings)
+    }
+
+    #[test]
+    fn math_to_mathml() {
+        let (html, warnings) = render_markdown("Energy $E = mc^2$ and\n\n$$\\sqrt{2}$$\n");
+
+        assert_eq!(
+            html,
+            "<p>Energy <math display=\"inline\">\
+             <mi>E</mi><mo>=</mo><mi>m</mi><msup><mi>c</mi><mn>2</mn></msup></math> and</p>\n\
+             <p><math display=\"block\"><msqrt><mrow><mn>2</mn></mrow></msqrt></math></p>\n"
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn malformed_math() {
+        let (html, warnings) = render_markdown("Broken $\\frac{1}$ formula\n");
+
+        assert_eq!(
+            html,
+            "<p>Broken <code class=\"math-error\">$\\frac{1}$</code> formula</p>\n"
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("malformed formula \"\\\\frac{1}\": parsing error:"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+use crate::Error;
+
+/// Directories that are never part of the wiki content.
+pub const DEFAULT_IGNORED: &[&str] = &[
+    ".cargo",
+    ".git",
+    ".github",
+    ".mwp-cache",
+    ".obsidian",
+    "dist",
+    "mwp-cli",
+    "node_modules",
+    "public",
+    "static",
+    "target",
+    "vendor",
+];
+
+/// Name of the project specific ignore file, using the `.gitignore` syntax.
+pub const IGNORE_FILE: &str = ".mwpignore";
+
+/// Walks the wiki tree while honoring ignore rules.
+///
+/// Entries whose name is in the ignored list are skipped, as are paths matched
+/// by `.gitignore` and `.mwpignore` files found in the tree.
+#[derive(Clone, Debug)]
+pub struct Walker {
+    root: PathBuf,
+    ignored: Vec<String>,
+    gitignore: bool,
+}
+
+impl Walker {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Walker {
+            root: root.as_ref().to_path_buf(),
+            ignored: DEFAULT_IGNORED
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            gitignore: true,
+        }
+    }
+
+    /// Root directory of the walk.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Skips files and directories with the given names, on top of the built-in list.
+    pub fn ignore<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.ignored.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether to honor `.gitignore` files, enabled by default.
+    pub fn gitignore(mut self, enabled: bool) -> Self {
+        self.gitignore = enabled;
+        self
+    }
+
+    /// Paths of all markdown files that aren't ignored.
+    pub fn markdown_files(&self) -> impl Iterator<Item = Result<PathBuf, Error>> + use<> {
+        self.files().filter(|entry| {
+            entry.as_ref().map_or(true, |path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+            })
+        })
+    }
+
+    /// Paths of all files that aren't ignored.
+    pub fn files(&self) -> impl Iterator<Item = Result<PathBuf, Error>> + use<> {
+        let ignored = self.ignored.clone();
+        let root = self.root.clone();
+
+        WalkBuilder::new(&self.root)
+            .hidden(false)
+            .ignore(false)
+            .parents(true)
+            .git_global(false)
+            .git_ignore(self.gitignore)
+            .git_exclude(self.gitignore)
+            .require_git(false)
+            .add_custom_ignore_filename(IGNORE_FILE)
+            .filter_entry(move |entry| {
+                entry.depth() == 0
+                    || entry
+                        .file_name()
+                        .to_str()
+                        .is_none_or(|name| !ignored.iter().any(|ignored| ignored == name))
+            })
+            .build()
+            .filter_map(move |entry| match entry {
+                Ok(entry) if entry.file_type().is_some_and(|ft| ft.is_file()) => {
+                    Some(Ok(entry.into_path()))
+                }
+                Ok(_) => None,
+                Err(source) => Some(Err(Error::Walk {
+                    path: error_path(&source).unwrap_or(&root).to_path_buf(),
+                    source,
+                })),
+            })
+    }
+}
+
+/// Path the walk error occurred at, if it carries one.
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithLineNumber { err, .. } | ignore::Error::WithDepth { err, .. } => {
+            error_path(err)
+        }
+        ignore::Error::Partial(errs) => errs.iter().find_map(error_path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn walker_honors_ignore_rules() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for dir in ["notes", "drafts", "node_modules/pkg", "dist", "tools"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "index.md",
+            "notes/a.md",
+            "notes/scratch.md",
+            "drafts/b.md",
+            "node_modules/pkg/README.md",
+            "dist/index.md",
+            "tools/git.md",
+            "tools/logo.png",
+        ] {
+            fs::write(root.join(file), "# Page\n").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "drafts/\n").unwrap();
+        fs::write(root.join(IGNORE_FILE), "**/scratch.md\n").unwrap();
+
+        let collect = |walker: Walker| {
+            let mut files: Vec<String> = walker
+                .markdown_files()
+                .map(|path| {
+                    path.unwrap()
+                        .strip_prefix(root)
+                        .unwrap()
+                        .display()
+                        .to_string()
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(
+            collect(Walker::new(root)),
+            vec!["index.md", "notes/a.md", "tools/git.md"]
+        );
+        assert_eq!(
+            collect(Walker::new(root).ignore(["tools"]).gitignore(false)),
+            vec!["drafts/b.md", "index.md", "notes/a.md"]
+        );
+    }
+}
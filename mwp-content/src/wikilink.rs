@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use pulldown_cmark_escape::{FmtWriter, escape_href, escape_html};
+use serde::{Deserialize, Serialize};
+
+use crate::{Content, Page, slugify};
+
+/// A `[[target]]` or `[[target|label]]` style link found on a page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WikiLink {
+    /// Page the link points to, given as a title, path or alias.
+    pub target: String,
+
+    /// Heading on the target page, without the leading `#`.
+    pub fragment: Option<String>,
+
+    /// Text the link is rendered with.
+    pub label: String,
+
+    /// Path of the page the target resolved to, filled in by [`Content`].
+    pub resolved: Option<String>,
+}
+
+impl WikiLink {
+    pub(crate) fn new(destination: &str) -> Self {
+        let (target, fragment) = match destination.split_once('#') {
+            Some((target, fragment)) => (target, Some(fragment.trim().to_string())),
+            None => (destination, None),
+        };
+
+        WikiLink {
+            target: target.trim().to_string(),
+            fragment,
+            label: String::new(),
+            resolved: None,
+        }
+    }
+}
+
+/// Wiki link whose target doesn't match any page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnresolvedLink {
+    /// Path of the page that contains the link.
+    pub page: String,
+
+    /// Target as written in the link.
+    pub target: String,
+}
+
+/// Opening tag emitted in place of a wiki link until the link is resolved.
+pub(crate) fn placeholder(index: usize) -> String {
+    format!("<a data-wikilink=\"{index}\">")
+}
+
+/// Maps lowercased titles, aliases and file names to page paths.
+pub(crate) fn build_names(pages: &HashMap<String, Page>) -> HashMap<String, String> {
+    let mut sorted: Vec<&Page> = pages.values().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut names = HashMap::new();
+    for page in &sorted {
+        if !page.title.is_empty() {
+            names
+                .entry(page.title.to_lowercase())
+                .or_insert_with(|| page.path.clone());
+        }
+    }
+    for page in &sorted {
+        for alias in &page.meta.aliases {
+            names
+                .entry(alias.to_lowercase())
+                .or_insert_with(|| page.path.clone());
+        }
+    }
+    for page in &sorted {
+        if let Some((_, name)) = page.path.rsplit_once('/')
+            && !name.is_empty()
+        {
+            names
+                .entry(name.to_lowercase())
+                .or_insert_with(|| page.path.clone());
+        }
+    }
+    names
+}
+
+impl Content {
+    /// Finds the page a wiki link target written on `from` refers to.
+    ///
+    /// Targets are matched as an absolute path, a path relative to `from`, and
+    /// then case-insensitively against page titles, aliases and file names. An
+    /// empty target refers to `from` itself.
+    pub fn resolve(&self, from: &Page, target: &str) -> Option<&Page> {
+        let target = target.trim();
+        if target.is_empty() {
+            return self.get(&from.path);
+        }
+
+        let target = target.strip_suffix(".md").unwrap_or(target);
+        let target = target.trim_end_matches('/');
+        if target.starts_with('/') {
+            return self.get(target);
+        }
+
+        let parent = from.path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let base = from.path.trim_end_matches('/');
+        let candidates = [
+            format!("{parent}/{target}"),
+            format!("{base}/{target}"),
+            format!("/{target}"),
+        ];
+        if let Some(page) = candidates.iter().find_map(|path| self.get(path)) {
+            return Some(page);
+        }
+
+        self.names
+            .get(&target.to_lowercase())
+            .and_then(|path| self.get(path))
+    }
+
+    /// Wiki links and embeds that didn't resolve to any page.
+    pub fn unresolved_links(&self) -> &[UnresolvedLink] {
+        &self.unresolved
+    }
+
+    /// Resolves wiki links on all pages and rewrites their placeholders into anchors.
+    pub(crate) fn resolve_wiki_links(&mut self) {
+        let mut resolved: Vec<(String, Vec<Option<String>>)> = Vec::new();
+        for page in self.pages.values() {
+            if page.wiki_links.is_empty() {
+                continue;
+            }
+            let targets = page
+                .wiki_links
+                .iter()
+                .map(|link| {
+                    self.resolve(page, &link.target)
+                        .map(|target| target.path.clone())
+                })
+                .collect();
+            resolved.push((page.path.clone(), targets));
+        }
+
+        let mut unresolved = Vec::new();
+        for (path, targets) in resolved {
+            let Some(page) = self.pages.get_mut(&path) else {
+                continue;
+            };
+
+            for (index, (link, target)) in page.wiki_links.iter_mut().zip(targets).enumerate() {
+                let anchor = match &target {
+                    Some(target) => {
+                        let mut href = page_url(target);
+                        if let Some(fragment) = &link.fragment {
+                            href.push('#');
+                            href.push_str(&slugify(fragment));
+                        }
+                        let mut anchor = String::from("<a class=\"wikilink\" href=\"");
+                        escape_href(FmtWriter(&mut anchor), &href).expect("write href");
+                        anchor.push_str("\">");
+                        anchor
+                    }
+                    None => {
+                        unresolved.push(UnresolvedLink {
+                            page: path.clone(),
+                            target: link.target.clone(),
+                        });
+                        let mut anchor =
+                            String::from("<a class=\"wikilink missing\" title=\"Missing page: ");
+                        escape_html(FmtWriter(&mut anchor), &link.target).expect("write title");
+                        anchor.push_str("\">");
+                        anchor
+                    }
+                };
+                page.html = page.html.replacen(&placeholder(index), &anchor, 1);
+                link.resolved = target;
+            }
+        }
+
+        unresolved.sort_by(|a, b| (&a.page, &a.target).cmp(&(&b.page, &b.target)));
+        self.unresolved = unresolved;
+    }
+}
+
+/// URL a page with the given path is served at in the built site.
+pub fn page_url(path: &str) -> String {
+    format!("{}/", path.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(pages: &[(&str, &str)]) -> Content {
+        Content::from_pages(
+            pages
+                .iter()
+                .map(|(path, markdown)| Page::from(path, markdown.to_string()).unwrap()),
+        )
+    }
+
+    #[test]
+    fn resolve_by_title_path_and_alias() {
+        let content = content(&[
+            ("/tools/git", "# Git\n"),
+            ("/tools/jj", "---\naliases: [jujutsu]\n---\n# JJ\n"),
+            (
+                "/notes",
+                "[[Git]], [[tools/jj|JJ]], [[Jujutsu#Setup|setup]] and [[Nowhere]]\n",
+            ),
+        ]);
+
+        let notes = content.get("/notes").unwrap();
+        let resolved: Vec<Option<&str>> = notes
+            .wiki_links
+            .iter()
+            .map(|link| link.resolved.as_deref())
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                Some("/tools/git"),
+                Some("/tools/jj"),
+                Some("/tools/jj"),
+                None
+            ]
+        );
+        assert!(
+            notes
+                .html
+                .contains(r#"<a class="wikilink" href="/tools/jj/#setup">setup</a>"#)
+        );
+        assert!(
+            notes.html.contains(
+                r#"<a class="wikilink missing" title="Missing page: Nowhere">Nowhere</a>"#
+            )
+        );
+        assert_eq!(
+            content.unresolved_links(),
+            &[UnresolvedLink {
+                page: "/notes".into(),
+                target: "Nowhere".into(),
+            }]
+        );
+    }
+}